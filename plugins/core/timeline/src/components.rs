@@ -1,7 +1,7 @@
 use lunaris_ecs::prelude::*;
 use lunaris_api::{render::RawImage, util::error::Result};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct TimelineSpan {
     pub start: u64,
     pub end: u64,
@@ -12,14 +12,46 @@ pub struct Playhead {
     pub current: u64,
 }
 
-#[derive(Component, Debug)]
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
 pub struct TimelineElement {
     /// Track number of Timeline Element, or in other words, the Z-index.
     pub track_num: u64,
     pub position: TimelineSpan,
 }
 
-#[derive(Component, Debug)]
+/// Where a clip starts reading its source and how fast it plays through it.
+///
+/// Clips without this component start at source time 0 and play at normal speed.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct SourceOffset {
+    /// Source time, in ticks, shown at `TimelineElement.position.start`.
+    pub in_point: u64,
+    /// Playback speed. 1.0 plays the source in real time, 0.5 at half speed.
+    pub speed: f64,
+}
+
+impl Default for SourceOffset {
+    fn default() -> Self {
+        Self {
+            in_point: 0,
+            speed: 1.0,
+        }
+    }
+}
+
+impl SourceOffset {
+    /// Maps a global timeline tick to source time for a clip occupying `span`.
+    /// Returns `None` when the tick lies outside the clip.
+    pub fn source_tick(&self, span: &TimelineSpan, timeline_tick: u64) -> Option<u64> {
+        if timeline_tick < span.start || timeline_tick >= span.end {
+            return None;
+        }
+        let local = (timeline_tick - span.start) as f64 * self.speed;
+        Some((self.in_point as f64 + local).max(0.0).round() as u64)
+    }
+}
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
 pub struct BindTo {
     pub id: Entity,
 }
@@ -28,3 +60,25 @@ pub struct BindTo {
 pub struct Renderable {
     pub render_result: Result<RawImage>,
 }
+
+/// Whether a clip's media can be decoded, set by the plugin that reads it on the
+/// source entity (the clip itself or its `BindTo` target). Absent until checked.
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
+pub enum MediaState {
+    Online,
+    /// The file is missing or unreadable.
+    Offline {
+        reason: String,
+    },
+    /// The file exists but cannot be decoded.
+    Unsupported {
+        reason: String,
+    },
+}
+
+/// A named point on the timeline, spawned as its own entity. Clip edges snap to it.
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
+pub struct Marker {
+    pub tick: u64,
+    pub label: String,
+}
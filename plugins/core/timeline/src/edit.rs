@@ -0,0 +1,696 @@
+//! World-side clip edits. The GUI records where clips were when a gesture began
+//! and re-applies the whole gesture from there every frame, so rounding never
+//! accumulates while dragging.
+
+use crate::components::{SourceOffset, TimelineElement, TimelineSpan};
+use lunaris_ecs::prelude::*;
+
+/// Which end of a clip a trim moves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    Start,
+    End,
+}
+
+/// A clip's placement at one moment.
+#[derive(Debug, Clone, Copy)]
+pub struct ClipState {
+    pub entity: Entity,
+    pub track_num: u64,
+    pub position: TimelineSpan,
+    /// `None` when the clip has no `SourceOffset`.
+    pub offset: Option<SourceOffset>,
+}
+
+impl ClipState {
+    pub fn capture(world: &World, entity: Entity) -> Option<Self> {
+        let el = world.get::<TimelineElement>(entity)?;
+        Some(Self {
+            entity,
+            track_num: el.track_num,
+            position: el.position,
+            offset: world.get::<SourceOffset>(entity).copied(),
+        })
+    }
+
+    /// Tick of one of the clip's edges.
+    pub fn edge(&self, edge: Edge) -> u64 {
+        match edge {
+            Edge::Start => self.position.start,
+            Edge::End => self.position.end,
+        }
+    }
+
+    /// Clips on the same track that start at or after this one ends.
+    pub fn following(&self, world: &mut World) -> Vec<Self> {
+        let mut q = world.query::<(Entity, &TimelineElement)>();
+        let ents: Vec<Entity> = q
+            .iter(world)
+            .filter(|(e, el)| {
+                *e != self.entity
+                    && el.track_num == self.track_num
+                    && el.position.start >= self.position.end
+            })
+            .map(|(e, _)| e)
+            .collect();
+        ents.into_iter()
+            .filter_map(|e| Self::capture(world, e))
+            .collect()
+    }
+
+    /// The clips on the same track that end exactly where this one starts and
+    /// start exactly where it ends.
+    pub fn neighbours(&self, world: &mut World) -> (Option<Self>, Option<Self>) {
+        let mut q = world.query::<(Entity, &TimelineElement)>();
+        let (mut left, mut right) = (None, None);
+        for (e, el) in q.iter(world) {
+            if e == self.entity || el.track_num != self.track_num {
+                continue;
+            }
+            if el.position.end == self.position.start {
+                left = Some(e);
+            } else if el.position.start == self.position.end {
+                right = Some(e);
+            }
+        }
+        (
+            left.and_then(|e| Self::capture(world, e)),
+            right.and_then(|e| Self::capture(world, e)),
+        )
+    }
+
+    fn shifted(&self, delta: i64) -> Self {
+        Self {
+            position: TimelineSpan {
+                start: self.position.start.saturating_add_signed(delta),
+                end: self.position.end.saturating_add_signed(delta),
+            },
+            ..*self
+        }
+    }
+
+    /// Writes this state back onto its entity.
+    pub fn restore(&self, world: &mut World) {
+        let Ok(mut entity) = world.get_entity_mut(self.entity) else {
+            return;
+        };
+        if let Some(mut el) = entity.get_mut::<TimelineElement>() {
+            el.track_num = self.track_num;
+            el.position = self.position;
+        }
+        match self.offset {
+            Some(offset) => {
+                entity.insert(offset);
+            }
+            None => {
+                entity.remove::<SourceOffset>();
+            }
+        }
+    }
+}
+
+/// Moves `clips` by `delta` ticks and `tracks` rows from their captured
+/// positions. The offsets are clamped so no clip goes before tick 0 or above
+/// track 0. A move that would overlap a clip on a destination track lands on
+/// the nearest offset where everything fits instead, next to it or in the
+/// nearest gap. The applied values are returned.
+pub fn move_clips(world: &mut World, clips: &[ClipState], delta: i64, tracks: i64) -> (i64, i64) {
+    let min_start = clips.iter().map(|c| c.position.start).min().unwrap_or(0);
+    let min_track = clips.iter().map(|c| c.track_num).min().unwrap_or(0);
+    let min_delta = -(min_start as i64);
+    let tracks = tracks.max(-(min_track as i64));
+    let delta = free_delta(world, clips, delta.max(min_delta), tracks, min_delta);
+    for clip in clips {
+        let moved = ClipState {
+            track_num: clip.track_num.saturating_add_signed(tracks),
+            ..clip.shifted(delta)
+        };
+        moved.restore(world);
+    }
+    (delta, tracks)
+}
+
+/// The offset closest to `delta`, and not below `min`, at which none of
+/// `clips` moved `tracks` rows overlaps a clip outside the move. Overlaps a
+/// clip already had on its own track do not count, so picking up a clip
+/// never pushes it away.
+fn free_delta(world: &mut World, clips: &[ClipState], delta: i64, tracks: i64, min: i64) -> i64 {
+    let mut q = world.query::<(Entity, &TimelineElement)>();
+    let still: Vec<(u64, TimelineSpan)> = q
+        .iter(world)
+        .filter(|(e, _)| clips.iter().all(|c| c.entity != *e))
+        .map(|(_, el)| (el.track_num, el.position))
+        .collect();
+    // Offsets strictly between `lo` and `hi` overlap a clip that stays.
+    let mut blocked: Vec<(i64, i64)> = Vec::new();
+    for clip in clips {
+        let track = clip.track_num.saturating_add_signed(tracks);
+        for &(track_num, span) in &still {
+            if track_num != track || (tracks == 0 && overlaps(clip.position, span)) {
+                continue;
+            }
+            blocked.push((
+                span.start as i64 - clip.position.end as i64,
+                span.end as i64 - clip.position.start as i64,
+            ));
+        }
+    }
+    let fits = |d: i64| blocked.iter().all(|&(lo, hi)| d <= lo || d >= hi);
+    // Past the last blocked range everything fits, so one of these does.
+    std::iter::once(delta)
+        .chain(blocked.iter().flat_map(|&(lo, hi)| [lo, hi]))
+        .filter(|&d| d >= min && fits(d))
+        .min_by_key(|&d| ((d - delta).abs(), d.abs()))
+        .unwrap_or(delta)
+}
+
+fn overlaps(a: TimelineSpan, b: TimelineSpan) -> bool {
+    a.start < b.end && b.start < a.end
+}
+
+/// Moves one edge of `clip` by `delta` ticks from its captured position.
+///
+/// Trimming the start also moves the source in-point, so the frames that stay
+/// visible do not shift. The start cannot move before source time 0 and
+/// neither edge can cross the other. Returns the applied offset.
+pub fn trim_clip(world: &mut World, clip: &ClipState, edge: Edge, delta: i64) -> i64 {
+    let trimmed = trimmed(clip, edge, delta);
+    trimmed.restore(world);
+    trim_applied(clip, &trimmed, edge)
+}
+
+/// Ripple trim: trims `clip` like [`trim_clip`], but keeps its start in place
+/// and shifts `following` (see [`ClipState::following`]) by the change in
+/// length, so no gap opens or closes behind it. Returns the applied offset of
+/// the trimmed edge.
+pub fn ripple_trim(
+    world: &mut World,
+    clip: &ClipState,
+    following: &[ClipState],
+    edge: Edge,
+    delta: i64,
+) -> i64 {
+    let mut trimmed = trimmed(clip, edge, delta);
+    let applied = trim_applied(clip, &trimmed, edge);
+    let growth = match edge {
+        Edge::Start => {
+            // Pull the tail in by as much as the head was trimmed.
+            trimmed.position = TimelineSpan {
+                start: clip.position.start,
+                end: clip.position.end.saturating_add_signed(-applied),
+            };
+            -applied
+        }
+        Edge::End => applied,
+    };
+    trimmed.restore(world);
+    for clip in following {
+        clip.shifted(growth).restore(world);
+    }
+    applied
+}
+
+/// Ripple delete: despawns `entities` and moves every later clip on the same
+/// track back by the time removed before it. Overlapping removed clips count
+/// once, so no clip is shifted past tick 0.
+pub fn ripple_delete(world: &mut World, entities: &[Entity]) {
+    let removed: Vec<ClipState> = entities
+        .iter()
+        .filter_map(|&e| ClipState::capture(world, e))
+        .collect();
+    for clip in &removed {
+        world.despawn(clip.entity);
+    }
+    let mut q = world.query::<&mut TimelineElement>();
+    for mut el in q.iter_mut(world) {
+        let before = removed
+            .iter()
+            .filter(|r| r.track_num == el.track_num && r.position.end <= el.position.start)
+            .map(|r| r.position);
+        let shift = covered(before);
+        if shift > 0 {
+            el.position.start -= shift;
+            el.position.end -= shift;
+        }
+    }
+}
+
+/// Length of the union of `spans`.
+fn covered(spans: impl Iterator<Item = TimelineSpan>) -> u64 {
+    let mut spans: Vec<TimelineSpan> = spans.collect();
+    spans.sort_by_key(|s| s.start);
+    let mut total = 0;
+    let mut reached = 0;
+    for span in spans {
+        let start = span.start.max(reached);
+        if span.end > start {
+            total += span.end - start;
+            reached = span.end;
+        }
+    }
+    total
+}
+
+/// Cuts every clip in `entities` that spans `tick` in two. The original keeps
+/// the part before `tick`. The part from `tick` on is a new entity cloned from
+/// the clip, so it shares its source and `BindTo`, with an in-point that picks
+/// up where the first part stops. Returns the new entities.
+pub fn split(world: &mut World, entities: &[Entity], tick: u64) -> Vec<Entity> {
+    let mut added = Vec::new();
+    for &entity in entities {
+        let Some(clip) = ClipState::capture(world, entity) else {
+            continue;
+        };
+        let span = clip.position;
+        if tick <= span.start || tick >= span.end {
+            continue;
+        }
+        let offset = clip.offset.unwrap_or_default();
+        let Some(in_point) = offset.source_tick(&span, tick) else {
+            continue;
+        };
+        let tail = world.entity_mut(entity).clone_and_spawn();
+        ClipState {
+            entity: tail,
+            position: TimelineSpan {
+                start: tick,
+                ..span
+            },
+            offset: Some(SourceOffset { in_point, ..offset }),
+            ..clip
+        }
+        .restore(world);
+        ClipState {
+            position: TimelineSpan { end: tick, ..span },
+            ..clip
+        }
+        .restore(world);
+        added.push(tail);
+    }
+    added
+}
+
+/// Roll edit: moves the cut between `left` and the clip directly after it,
+/// `right`, by `delta` ticks. Neither clip moves or changes what it shows away
+/// from the cut. Returns the applied offset.
+pub fn roll(world: &mut World, left: &ClipState, right: &ClipState, delta: i64) -> i64 {
+    let delta = trim_delta(right, Edge::Start, trim_delta(left, Edge::End, delta));
+    trimmed(left, Edge::End, delta).restore(world);
+    trimmed(right, Edge::Start, delta).restore(world);
+    delta
+}
+
+/// Slip edit: shows source material `delta` ticks later (earlier when
+/// negative) in `clip` without moving or resizing it. The in-point stops at
+/// source time 0. Returns the applied offset.
+pub fn slip(world: &mut World, clip: &ClipState, delta: i64) -> i64 {
+    let offset = clip.offset.unwrap_or_default();
+    let shift = (delta as f64 * offset.speed).round() as i64;
+    let in_point = offset.in_point.saturating_add_signed(shift);
+    ClipState {
+        offset: (clip.offset.is_some() || in_point != offset.in_point)
+            .then_some(SourceOffset { in_point, ..offset }),
+        ..*clip
+    }
+    .restore(world);
+    ((in_point as f64 - offset.in_point as f64) / offset.speed).round() as i64
+}
+
+/// Slide edit: moves `clip` by `delta` ticks, keeping its source range, while
+/// the clip before it gets longer or shorter at its end and the clip after it
+/// at its start so both stay adjacent. Returns the applied offset.
+pub fn slide(
+    world: &mut World,
+    clip: &ClipState,
+    left: Option<&ClipState>,
+    right: Option<&ClipState>,
+    delta: i64,
+) -> i64 {
+    let mut delta = delta.max(-(clip.position.start as i64));
+    if let Some(left) = left {
+        delta = trim_delta(left, Edge::End, delta);
+    }
+    if let Some(right) = right {
+        delta = trim_delta(right, Edge::Start, delta);
+    }
+    if let Some(left) = left {
+        trimmed(left, Edge::End, delta).restore(world);
+    }
+    if let Some(right) = right {
+        trimmed(right, Edge::Start, delta).restore(world);
+    }
+    clip.shifted(delta).restore(world);
+    delta
+}
+
+/// How far a trim of `edge` by `delta` can actually go. Each limit is a range
+/// around 0, so clamping through several of these in turn satisfies them all.
+fn trim_delta(clip: &ClipState, edge: Edge, delta: i64) -> i64 {
+    trim_applied(clip, &trimmed(clip, edge, delta), edge)
+}
+
+fn trim_applied(clip: &ClipState, trimmed: &ClipState, edge: Edge) -> i64 {
+    trimmed.edge(edge) as i64 - clip.edge(edge) as i64
+}
+
+fn trimmed(clip: &ClipState, edge: Edge, delta: i64) -> ClipState {
+    let span = clip.position;
+    match edge {
+        Edge::Start => {
+            let offset = clip.offset.unwrap_or_default();
+            // The earliest start that still maps to source time 0.
+            let lower = (span.start as f64 - offset.in_point as f64 / offset.speed)
+                .max(0.0)
+                .ceil() as u64;
+            let start = span
+                .start
+                .saturating_add_signed(delta)
+                .clamp(lower.min(span.start), span.end.saturating_sub(1).max(lower));
+            let moved = start as f64 - span.start as f64;
+            let in_point = (offset.in_point as f64 + moved * offset.speed)
+                .max(0.0)
+                .round() as u64;
+            ClipState {
+                position: TimelineSpan { start, ..span },
+                offset: (clip.offset.is_some() || start != span.start)
+                    .then_some(SourceOffset { in_point, ..offset }),
+                ..*clip
+            }
+        }
+        Edge::End => ClipState {
+            position: TimelineSpan {
+                end: span.end.saturating_add_signed(delta).max(span.start + 1),
+                ..span
+            },
+            ..*clip
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clip(world: &mut World, track_num: u64, start: u64, end: u64) -> Entity {
+        world
+            .spawn(TimelineElement {
+                track_num,
+                position: TimelineSpan { start, end },
+            })
+            .id()
+    }
+
+    /// A clip with a `SourceOffset`.
+    fn source_clip(world: &mut World, start: u64, end: u64, in_point: u64, speed: f64) -> Entity {
+        let entity = clip(world, 0, start, end);
+        world
+            .entity_mut(entity)
+            .insert(SourceOffset { in_point, speed });
+        entity
+    }
+
+    fn state(world: &World, entity: Entity) -> ClipState {
+        ClipState::capture(world, entity).unwrap()
+    }
+
+    fn span(world: &World, entity: Entity) -> (u64, u64) {
+        let el = world.get::<TimelineElement>(entity).unwrap();
+        (el.position.start, el.position.end)
+    }
+
+    fn in_point(world: &World, entity: Entity) -> Option<u64> {
+        world.get::<SourceOffset>(entity).map(|o| o.in_point)
+    }
+
+    #[test]
+    fn move_stops_at_tick_zero_and_track_zero() {
+        let mut world = World::new();
+        let a = clip(&mut world, 1, 100, 200);
+        let b = clip(&mut world, 2, 50, 80);
+        let clips = [state(&world, a), state(&world, b)];
+
+        assert_eq!(move_clips(&mut world, &clips, -500, -5), (-50, -1));
+        assert_eq!(span(&world, a), (50, 150));
+        assert_eq!(span(&world, b), (0, 30));
+        assert_eq!(state(&world, a).track_num, 0);
+        assert_eq!(state(&world, b).track_num, 1);
+
+        // Each gesture starts over from the captured positions.
+        assert_eq!(move_clips(&mut world, &clips, 20, 1), (20, 1));
+        assert_eq!(span(&world, a), (120, 220));
+        assert_eq!(state(&world, b).track_num, 3);
+    }
+
+    #[test]
+    fn move_stops_next_to_a_clip_in_the_way() {
+        let mut world = World::new();
+        let first = clip(&mut world, 0, 0, 100);
+        let second = clip(&mut world, 0, 100, 150);
+        let other = clip(&mut world, 0, 300, 400);
+        let clips = [state(&world, first), state(&world, second)];
+
+        // Clips moved together do not block each other.
+        assert_eq!(move_clips(&mut world, &clips, 100, 0), (100, 0));
+        assert_eq!(move_clips(&mut world, &clips, 200, 0), (150, 0));
+        assert_eq!(span(&world, first), (150, 250));
+        assert_eq!(span(&world, second), (250, 300));
+        assert_eq!(span(&world, other), (300, 400));
+
+        // Dragged far enough, they jump past it.
+        assert_eq!(move_clips(&mut world, &clips, 380, 0), (400, 0));
+        assert_eq!(span(&world, first), (400, 500));
+    }
+
+    #[test]
+    fn move_to_another_track_lands_in_the_nearest_gap() {
+        let mut world = World::new();
+        let moved = clip(&mut world, 0, 0, 40);
+        clip(&mut world, 1, 0, 100);
+        clip(&mut world, 1, 150, 300);
+        let clips = [state(&world, moved)];
+
+        assert_eq!(move_clips(&mut world, &clips, 120, 1), (110, 1));
+        assert_eq!(span(&world, moved), (110, 150));
+        assert_eq!(state(&world, moved).track_num, 1);
+
+        // The start of the timeline is taken, so the gap is the nearest fit.
+        assert_eq!(move_clips(&mut world, &clips, -30, 1), (100, 1));
+        assert_eq!(span(&world, moved), (100, 140));
+    }
+
+    #[test]
+    fn move_keeps_overlaps_a_clip_already_had() {
+        let mut world = World::new();
+        let moved = clip(&mut world, 0, 0, 100);
+        clip(&mut world, 0, 50, 150);
+        let clips = [state(&world, moved)];
+
+        assert_eq!(move_clips(&mut world, &clips, 0, 0), (0, 0));
+        assert_eq!(move_clips(&mut world, &clips, 10, 0), (10, 0));
+        assert_eq!(span(&world, moved), (10, 110));
+    }
+
+    #[test]
+    fn ripple_delete_counts_overlapping_clips_once() {
+        let mut world = World::new();
+        let a = clip(&mut world, 0, 0, 100);
+        let b = clip(&mut world, 0, 50, 150);
+        let later = clip(&mut world, 0, 150, 200);
+        let other_track = clip(&mut world, 1, 150, 200);
+        ripple_delete(&mut world, &[a, b]);
+        assert_eq!(span(&world, later), (0, 50));
+        assert_eq!(span(&world, other_track), (150, 200));
+    }
+
+    #[test]
+    fn ripple_delete_skips_the_gap_between_removed_clips() {
+        let mut world = World::new();
+        let a = clip(&mut world, 0, 0, 100);
+        let middle = clip(&mut world, 0, 100, 150);
+        let b = clip(&mut world, 0, 200, 300);
+        let last = clip(&mut world, 0, 300, 320);
+        ripple_delete(&mut world, &[a, b]);
+        assert_eq!(span(&world, middle), (0, 50));
+        assert_eq!(span(&world, last), (100, 120));
+    }
+
+    #[test]
+    fn trim_start_stops_at_source_time_zero() {
+        let mut world = World::new();
+        let e = source_clip(&mut world, 100, 200, 20, 1.0);
+        let clip = state(&world, e);
+        assert_eq!(trim_clip(&mut world, &clip, Edge::Start, -50), -20);
+        assert_eq!(span(&world, e), (80, 200));
+        assert_eq!(in_point(&world, e), Some(0));
+    }
+
+    #[test]
+    fn trim_start_scales_the_in_point_by_speed() {
+        let mut world = World::new();
+        let e = source_clip(&mut world, 100, 200, 40, 2.0);
+        let clip = state(&world, e);
+        // 40 source ticks at double speed last 20 timeline ticks.
+        assert_eq!(trim_clip(&mut world, &clip, Edge::Start, -50), -20);
+        assert_eq!(in_point(&world, e), Some(0));
+        assert_eq!(trim_clip(&mut world, &clip, Edge::Start, 30), 30);
+        assert_eq!(span(&world, e), (130, 200));
+        assert_eq!(in_point(&world, e), Some(100));
+    }
+
+    #[test]
+    fn trim_never_crosses_the_other_edge() {
+        let mut world = World::new();
+        let e = clip(&mut world, 0, 100, 200);
+        let clip = state(&world, e);
+        assert_eq!(trim_clip(&mut world, &clip, Edge::Start, 500), 99);
+        assert_eq!(span(&world, e), (199, 200));
+        assert_eq!(trim_clip(&mut world, &clip, Edge::End, -500), -99);
+        assert_eq!(span(&world, e), (100, 101));
+    }
+
+    #[test]
+    fn trimmed_leaves_clips_without_offset_alone_until_the_start_moves() {
+        let mut world = World::new();
+        let e = clip(&mut world, 0, 100, 200);
+        let clip = state(&world, e);
+        assert_eq!(trimmed(&clip, Edge::Start, 0).offset, None);
+        assert_eq!(trimmed(&clip, Edge::End, 10).offset, None);
+        let moved = trimmed(&clip, Edge::Start, 10);
+        assert_eq!(moved.offset.map(|o| o.in_point), Some(10));
+    }
+
+    #[test]
+    fn ripple_trim_end_shifts_following_clips() {
+        let mut world = World::new();
+        let e = clip(&mut world, 0, 0, 100);
+        let next = clip(&mut world, 0, 100, 150);
+        let last = clip(&mut world, 0, 200, 250);
+        let clip = state(&world, e);
+        let following = clip.following(&mut world);
+        assert_eq!(
+            ripple_trim(&mut world, &clip, &following, Edge::End, 20),
+            20
+        );
+        assert_eq!(span(&world, e), (0, 120));
+        assert_eq!(span(&world, next), (120, 170));
+        assert_eq!(span(&world, last), (220, 270));
+    }
+
+    #[test]
+    fn ripple_trim_start_keeps_the_clip_in_place() {
+        let mut world = World::new();
+        let e = source_clip(&mut world, 100, 200, 50, 1.0);
+        let next = clip(&mut world, 0, 200, 300);
+        let clip = state(&world, e);
+        let following = clip.following(&mut world);
+        assert_eq!(
+            ripple_trim(&mut world, &clip, &following, Edge::Start, 30),
+            30
+        );
+        assert_eq!(span(&world, e), (100, 170));
+        assert_eq!(in_point(&world, e), Some(80));
+        assert_eq!(span(&world, next), (170, 270));
+
+        // Growing the head stops at source time 0.
+        assert_eq!(
+            ripple_trim(&mut world, &clip, &following, Edge::Start, -80),
+            -50
+        );
+        assert_eq!(span(&world, e), (100, 250));
+        assert_eq!(in_point(&world, e), Some(0));
+        assert_eq!(span(&world, next), (250, 350));
+    }
+
+    #[test]
+    fn roll_moves_the_cut_between_two_clips() {
+        let mut world = World::new();
+        let left = source_clip(&mut world, 0, 100, 0, 1.0);
+        let right = source_clip(&mut world, 100, 200, 100, 1.0);
+        let (l, r) = (state(&world, left), state(&world, right));
+        assert_eq!(roll(&mut world, &l, &r, 20), 20);
+        assert_eq!(span(&world, left), (0, 120));
+        assert_eq!(span(&world, right), (120, 200));
+        assert_eq!(in_point(&world, right), Some(120));
+
+        // The right clip cannot shrink to nothing.
+        assert_eq!(roll(&mut world, &l, &r, 200), 99);
+        assert_eq!(span(&world, left), (0, 199));
+        assert_eq!(span(&world, right), (199, 200));
+    }
+
+    #[test]
+    fn roll_stops_where_the_right_clip_reaches_source_time_zero() {
+        let mut world = World::new();
+        let left = clip(&mut world, 0, 0, 100);
+        let right = source_clip(&mut world, 100, 200, 20, 2.0);
+        let (l, r) = (state(&world, left), state(&world, right));
+        assert_eq!(roll(&mut world, &l, &r, -50), -10);
+        assert_eq!(span(&world, left), (0, 90));
+        assert_eq!(span(&world, right), (90, 200));
+        assert_eq!(in_point(&world, right), Some(0));
+    }
+
+    #[test]
+    fn slip_moves_the_in_point_only() {
+        let mut world = World::new();
+        let e = source_clip(&mut world, 100, 200, 50, 1.0);
+        let clip = state(&world, e);
+        assert_eq!(slip(&mut world, &clip, 30), 30);
+        assert_eq!(in_point(&world, e), Some(80));
+        assert_eq!(slip(&mut world, &clip, -80), -50);
+        assert_eq!(in_point(&world, e), Some(0));
+        assert_eq!(span(&world, e), (100, 200));
+    }
+
+    #[test]
+    fn slip_scales_by_speed() {
+        let mut world = World::new();
+        let e = source_clip(&mut world, 100, 200, 50, 2.0);
+        let clip = state(&world, e);
+        assert_eq!(slip(&mut world, &clip, 10), 10);
+        assert_eq!(in_point(&world, e), Some(70));
+        assert_eq!(slip(&mut world, &clip, -40), -25);
+        assert_eq!(in_point(&world, e), Some(0));
+    }
+
+    #[test]
+    fn slide_keeps_neighbours_adjacent() {
+        let mut world = World::new();
+        let left = clip(&mut world, 0, 0, 100);
+        let middle = source_clip(&mut world, 100, 200, 0, 1.0);
+        let right = source_clip(&mut world, 200, 300, 200, 2.0);
+        let (l, m, r) = (
+            state(&world, left),
+            state(&world, middle),
+            state(&world, right),
+        );
+        assert_eq!(slide(&mut world, &m, Some(&l), Some(&r), 30), 30);
+        assert_eq!(span(&world, left), (0, 130));
+        assert_eq!(span(&world, middle), (130, 230));
+        assert_eq!(in_point(&world, middle), Some(0));
+        assert_eq!(span(&world, right), (230, 300));
+        assert_eq!(in_point(&world, right), Some(260));
+    }
+
+    #[test]
+    fn slide_stops_before_a_neighbour_vanishes() {
+        let mut world = World::new();
+        let left = clip(&mut world, 0, 0, 100);
+        let middle = clip(&mut world, 0, 100, 200);
+        let right = source_clip(&mut world, 200, 300, 200, 1.0);
+        let (l, m, r) = (
+            state(&world, left),
+            state(&world, middle),
+            state(&world, right),
+        );
+        assert_eq!(slide(&mut world, &m, Some(&l), Some(&r), 150), 99);
+        assert_eq!(span(&world, middle), (199, 299));
+        assert_eq!(span(&world, right), (299, 300));
+
+        assert_eq!(slide(&mut world, &m, Some(&l), Some(&r), -150), -99);
+        assert_eq!(span(&world, left), (0, 1));
+        assert_eq!(span(&world, middle), (1, 101));
+        assert_eq!(span(&world, right), (101, 300));
+        assert_eq!(in_point(&world, right), Some(101));
+    }
+}
@@ -0,0 +1,580 @@
+//! Undo and redo for timeline edits.
+//!
+//! An edit is recorded as a transaction: the tracked components of every entity
+//! it touches are cloned before and after, and undoing or redoing writes one
+//! side back. Which components are tracked is open, so plugins that put their
+//! own data on clips register it with [`track`] and the timeline never needs
+//! to know their types.
+//!
+//! Recording state rather than commands with their inverses is deliberate.
+//! Edits clamp against neighbours, tracks and tick 0, and plugins change clips
+//! in ways the timeline cannot see, so a hand-written inverse per operation
+//! would have to repeat every rule it undoes and could not cover foreign
+//! components at all. Restoring a snapshot is exact by construction. The price
+//! is two copies of each touched entity per edit, which
+//! [`EditHistory::limit_bytes`] bounds.
+
+use crate::components::{BindTo, Marker, SourceOffset, TimelineElement};
+use lunaris_ecs::prelude::*;
+use std::{
+    any::{Any, TypeId},
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+
+/// Memory the history may hold before dropping its oldest edits.
+pub const DEFAULT_HISTORY_BYTES: usize = 32 * 1024 * 1024;
+
+type Value = Box<dyn Any + Send + Sync>;
+
+#[derive(Clone)]
+struct Tracked {
+    type_id: TypeId,
+    size: Arc<dyn Fn(&Value) -> usize + Send + Sync>,
+    eq: fn(&Value, &Value) -> bool,
+    capture: fn(&World, Entity) -> Option<Value>,
+    restore: fn(&mut EntityWorldMut<'_>, Option<&Value>),
+}
+
+/// Component types that edits snapshot.
+#[derive(Resource, Clone, Default)]
+pub struct TrackedComponents(Vec<Tracked>);
+
+impl TrackedComponents {
+    pub fn track<T: Component + Clone + PartialEq>(&mut self) {
+        self.track_sized::<T>(|_| std::mem::size_of::<T>());
+    }
+
+    /// Like [`track`](Self::track), with `size` estimating the bytes a copy of
+    /// `T` holds, heap included, for the history's memory limit.
+    pub fn track_sized<T: Component + Clone + PartialEq>(&mut self, size: fn(&T) -> usize) {
+        if self.0.iter().any(|t| t.type_id == TypeId::of::<T>()) {
+            return;
+        }
+        self.0.push(Tracked {
+            type_id: TypeId::of::<T>(),
+            size: Arc::new(move |v| v.downcast_ref::<T>().map_or(0, size)),
+            eq: |a, b| a.downcast_ref::<T>() == b.downcast_ref::<T>(),
+            capture: |world, entity| world.get::<T>(entity).map(|c| Box::new(c.clone()) as Value),
+            restore: |entity, value| match value.and_then(|v| v.downcast_ref::<T>()) {
+                Some(c) => {
+                    entity.insert(c.clone());
+                }
+                None => {
+                    entity.remove::<T>();
+                }
+            },
+        });
+    }
+}
+
+/// Has edits snapshot `T` as part of a clip, so undo restores it. Each copy
+/// counts as `size_of::<T>()` against the memory limit; components that own
+/// heap data should use [`track_sized`].
+///
+/// Undoing a delete spawns a new entity holding only the tracked components.
+/// Anything else a plugin kept on the clip, such as state derived from its
+/// tracked components, is gone and has to be rebuilt by that plugin, which
+/// sees the clip as newly added. [`EditHistory::resolve`] maps old ids to new.
+pub fn track<T: Component + Clone + PartialEq>(world: &mut World) {
+    world
+        .get_resource_or_insert_with(TrackedComponents::default)
+        .track::<T>();
+}
+
+/// Like [`track`], with `size` estimating the bytes a copy of `T` holds.
+pub fn track_sized<T: Component + Clone + PartialEq>(world: &mut World, size: fn(&T) -> usize) {
+    world
+        .get_resource_or_insert_with(TrackedComponents::default)
+        .track_sized::<T>(size);
+}
+
+/// Registers the timeline's own clip components.
+pub(crate) fn track_timeline(world: &mut World) {
+    track::<TimelineElement>(world);
+    track::<SourceOffset>(world);
+    track::<BindTo>(world);
+    track_sized::<Marker>(world, |m| {
+        std::mem::size_of::<Marker>() + m.label.capacity()
+    });
+}
+
+/// One entity's tracked components, or `None` when it did not exist. Values
+/// line up with `TrackedComponents` as it was when captured.
+struct Snapshot {
+    entity: Entity,
+    components: Option<Vec<Option<Value>>>,
+}
+
+impl Snapshot {
+    fn capture(world: &World, tracked: &[Tracked], entity: Entity) -> Self {
+        let components = world
+            .get_entity(entity)
+            .is_ok()
+            .then(|| tracked.iter().map(|t| (t.capture)(world, entity)).collect());
+        Self { entity, components }
+    }
+
+    fn absent(entity: Entity) -> Self {
+        Self {
+            entity,
+            components: None,
+        }
+    }
+
+    /// Whether both hold the same components with equal values.
+    fn same(&self, other: &Self, tracked: &[Tracked]) -> bool {
+        let (Some(a), Some(b)) = (&self.components, &other.components) else {
+            return self.components.is_none() && other.components.is_none();
+        };
+        let equal = |((a, b), t): ((&Option<Value>, &Option<Value>), &Tracked)| match (a, b) {
+            (None, None) => true,
+            (Some(a), Some(b)) => (t.eq)(a, b),
+            _ => false,
+        };
+        a.len() == b.len() && a.iter().zip(b).zip(tracked).all(equal)
+    }
+
+    fn bytes(&self, tracked: &[Tracked]) -> usize {
+        let values = self.components.iter().flat_map(|values| {
+            values
+                .iter()
+                .zip(tracked)
+                .filter_map(|(v, t)| v.as_ref().map(|v| (t.size)(v)))
+        });
+        std::mem::size_of::<Self>() + values.sum::<usize>()
+    }
+}
+
+struct Record {
+    label: &'static str,
+    before: Vec<Snapshot>,
+    after: Vec<Snapshot>,
+    bytes: usize,
+}
+
+/// An edit being recorded, between [`begin`] and [`commit`].
+struct Transaction {
+    label: &'static str,
+    before: Vec<Snapshot>,
+}
+
+/// Undo and redo stacks, bounded by an estimate of the memory they hold.
+///
+/// [`Plugin::reset`](lunaris_api::plugin::Plugin::reset) clears the history
+/// unless `keep_on_reset` is set.
+#[derive(Resource)]
+pub struct EditHistory {
+    pub limit_bytes: usize,
+    pub keep_on_reset: bool,
+    undo: VecDeque<Record>,
+    redo: Vec<Record>,
+    open: Option<Transaction>,
+    bytes: usize,
+    /// Entities respawned by undo or redo, from the id they had before.
+    respawned: HashMap<Entity, Entity>,
+}
+
+impl Default for EditHistory {
+    fn default() -> Self {
+        Self {
+            limit_bytes: DEFAULT_HISTORY_BYTES,
+            keep_on_reset: false,
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            open: None,
+            bytes: 0,
+            respawned: HashMap::new(),
+        }
+    }
+}
+
+impl EditHistory {
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Name of the edit [`undo`] would revert.
+    pub fn undo_label(&self) -> Option<&'static str> {
+        self.undo.back().map(|r| r.label)
+    }
+
+    /// Name of the edit [`redo`] would reapply.
+    pub fn redo_label(&self) -> Option<&'static str> {
+        self.redo.last().map(|r| r.label)
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self {
+            limit_bytes: self.limit_bytes,
+            keep_on_reset: self.keep_on_reset,
+            ..Self::default()
+        };
+    }
+
+    /// Clears the history unless `keep_on_reset` is set.
+    pub fn reset(&mut self) {
+        if !self.keep_on_reset {
+            self.clear();
+        }
+    }
+
+    /// The entity `entity` lives on now, following respawns.
+    pub fn resolve(&self, mut entity: Entity) -> Entity {
+        while let Some(&next) = self.respawned.get(&entity) {
+            entity = next;
+        }
+        entity
+    }
+
+    fn push(&mut self, record: Record) {
+        self.bytes -= self.redo.drain(..).map(|r| r.bytes).sum::<usize>();
+        self.bytes += record.bytes;
+        self.undo.push_back(record);
+        // Always keep the latest edit, however large.
+        while self.bytes > self.limit_bytes && self.undo.len() > 1 {
+            let dropped = self
+                .undo
+                .pop_front()
+                .expect("undo stack has more than one edit");
+            self.bytes -= dropped.bytes;
+        }
+    }
+
+    fn apply(&mut self, world: &mut World, tracked: &[Tracked], snapshots: &[Snapshot]) {
+        for snap in snapshots {
+            let current = self.resolve(snap.entity);
+            let exists = world.get_entity(current).is_ok();
+            match &snap.components {
+                None => {
+                    if exists {
+                        world.despawn(current);
+                    }
+                }
+                Some(values) => {
+                    let target = if exists {
+                        current
+                    } else {
+                        let spawned = world.spawn_empty().id();
+                        self.respawned.insert(current, spawned);
+                        spawned
+                    };
+                    let mut entity = world.entity_mut(target);
+                    for (t, value) in tracked.iter().zip(values) {
+                        (t.restore)(&mut entity, value.as_ref());
+                    }
+                }
+            }
+        }
+        // Bindings saved before a respawn still name the old entity.
+        let mut q = world.query::<&mut BindTo>();
+        for mut bind in q.iter_mut(world) {
+            let id = self.resolve(bind.id);
+            if id != bind.id {
+                bind.id = id;
+            }
+        }
+    }
+}
+
+fn tracked(world: &World) -> Vec<Tracked> {
+    world
+        .get_resource::<TrackedComponents>()
+        .map(|t| t.0.clone())
+        .unwrap_or_default()
+}
+
+/// Starts recording an edit to `entities`, capturing them as they are now.
+/// While an edit is open, further calls add their entities to it, so nested
+/// operations become one undo step.
+pub fn begin(world: &mut World, label: &'static str, entities: impl IntoIterator<Item = Entity>) {
+    world.init_resource::<EditHistory>();
+    let tracked = tracked(world);
+    world.resource_scope(|world, mut history: Mut<EditHistory>| {
+        let tx = history.open.get_or_insert_with(|| Transaction {
+            label,
+            before: Vec::new(),
+        });
+        for entity in entities {
+            if !tx.before.iter().any(|s| s.entity == entity) {
+                tx.before.push(Snapshot::capture(world, &tracked, entity));
+            }
+        }
+    });
+}
+
+/// Adds entities spawned by the open edit, so undo despawns them.
+pub fn created(world: &mut World, entities: impl IntoIterator<Item = Entity>) {
+    if let Some(mut history) = world.get_resource_mut::<EditHistory>()
+        && let Some(tx) = history.open.as_mut()
+    {
+        tx.before.extend(entities.into_iter().map(Snapshot::absent));
+    }
+}
+
+/// Finishes the open edit and pushes it onto the undo stack, dropping the
+/// redo stack and, past the memory limit, the oldest edits. An edit that
+/// changed nothing, like a click that did not move a clip, is dropped and
+/// leaves both stacks as they were.
+pub fn commit(world: &mut World) {
+    world.init_resource::<EditHistory>();
+    let tracked = tracked(world);
+    world.resource_scope(|world, mut history: Mut<EditHistory>| {
+        let Some(tx) = history.open.take() else {
+            return;
+        };
+        let after: Vec<Snapshot> = tx
+            .before
+            .iter()
+            .map(|s| Snapshot::capture(world, &tracked, s.entity))
+            .collect();
+        let unchanged = tx
+            .before
+            .iter()
+            .zip(&after)
+            .all(|(b, a)| b.same(a, &tracked));
+        if unchanged {
+            return;
+        }
+        let bytes = tx
+            .before
+            .iter()
+            .chain(&after)
+            .map(|s| s.bytes(&tracked))
+            .sum();
+        history.push(Record {
+            label: tx.label,
+            before: tx.before,
+            after,
+            bytes,
+        });
+    });
+}
+
+/// Reverts the latest edit. Returns `false` when there is nothing to undo or
+/// an edit is still open.
+pub fn undo(world: &mut World) -> bool {
+    world.init_resource::<EditHistory>();
+    let tracked = tracked(world);
+    world.resource_scope(|world, mut history: Mut<EditHistory>| {
+        if history.open.is_some() {
+            return false;
+        }
+        let Some(record) = history.undo.pop_back() else {
+            return false;
+        };
+        history.apply(world, &tracked, &record.before);
+        history.redo.push(record);
+        true
+    })
+}
+
+/// Reapplies the latest undone edit. Returns `false` when there is nothing to
+/// redo or an edit is still open.
+pub fn redo(world: &mut World) -> bool {
+    world.init_resource::<EditHistory>();
+    let tracked = tracked(world);
+    world.resource_scope(|world, mut history: Mut<EditHistory>| {
+        if history.open.is_some() {
+            return false;
+        }
+        let Some(record) = history.redo.pop() else {
+            return false;
+        };
+        history.apply(world, &tracked, &record.after);
+        history.undo.push_back(record);
+        true
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::TimelineSpan;
+
+    fn timeline() -> World {
+        let mut world = World::new();
+        track_timeline(&mut world);
+        world
+    }
+
+    fn clip(world: &mut World, start: u64, end: u64) -> Entity {
+        world
+            .spawn(TimelineElement {
+                track_num: 0,
+                position: TimelineSpan { start, end },
+            })
+            .id()
+    }
+
+    fn span(world: &World, entity: Entity) -> (u64, u64) {
+        let el = world.get::<TimelineElement>(entity).unwrap();
+        (el.position.start, el.position.end)
+    }
+
+    fn move_to(world: &mut World, entity: Entity, start: u64, end: u64) {
+        world.get_mut::<TimelineElement>(entity).unwrap().position = TimelineSpan { start, end };
+    }
+
+    fn history(world: &World) -> &EditHistory {
+        world.resource::<EditHistory>()
+    }
+
+    #[test]
+    fn undo_and_redo_restore_components() {
+        let mut world = timeline();
+        let e = clip(&mut world, 0, 100);
+
+        begin(&mut world, "Move", [e]);
+        move_to(&mut world, e, 50, 150);
+        commit(&mut world);
+        assert_eq!(history(&world).undo_label(), Some("Move"));
+
+        assert!(undo(&mut world));
+        assert_eq!(span(&world, e), (0, 100));
+        assert_eq!(history(&world).redo_label(), Some("Move"));
+        assert!(!undo(&mut world));
+
+        assert!(redo(&mut world));
+        assert_eq!(span(&world, e), (50, 150));
+        assert!(!redo(&mut world));
+    }
+
+    #[test]
+    fn nested_edits_are_one_step() {
+        let mut world = timeline();
+        let a = clip(&mut world, 0, 100);
+        let b = clip(&mut world, 100, 200);
+
+        begin(&mut world, "Roll", [a]);
+        begin(&mut world, "Trim", [b, a]);
+        move_to(&mut world, a, 0, 80);
+        move_to(&mut world, b, 80, 200);
+        assert!(!undo(&mut world), "nothing is undone while an edit is open");
+        commit(&mut world);
+
+        assert_eq!(history(&world).undo_label(), Some("Roll"));
+        assert!(undo(&mut world));
+        assert_eq!(span(&world, a), (0, 100));
+        assert_eq!(span(&world, b), (100, 200));
+        assert!(!history(&world).can_undo());
+    }
+
+    #[test]
+    fn unchanged_edit_is_dropped() {
+        let mut world = timeline();
+        let e = clip(&mut world, 0, 100);
+
+        begin(&mut world, "Move", [e]);
+        move_to(&mut world, e, 50, 150);
+        commit(&mut world);
+        assert!(undo(&mut world));
+
+        // A click that moves nothing keeps the redo stack.
+        begin(&mut world, "Move", [e]);
+        commit(&mut world);
+        assert!(!history(&world).can_undo());
+        assert!(history(&world).can_redo());
+    }
+
+    #[test]
+    fn created_entities_are_despawned_and_respawned() {
+        let mut world = timeline();
+
+        begin(&mut world, "Add", []);
+        let e = clip(&mut world, 10, 20);
+        created(&mut world, [e]);
+        commit(&mut world);
+
+        assert!(undo(&mut world));
+        assert!(world.get_entity(e).is_err());
+
+        assert!(redo(&mut world));
+        let respawned = history(&world).resolve(e);
+        assert_ne!(respawned, e);
+        assert_eq!(span(&world, respawned), (10, 20));
+    }
+
+    #[test]
+    fn bindings_follow_respawned_entities() {
+        let mut world = timeline();
+        let video = clip(&mut world, 0, 100);
+        let audio = world
+            .spawn((
+                TimelineElement {
+                    track_num: 1,
+                    position: TimelineSpan { start: 0, end: 100 },
+                },
+                BindTo { id: video },
+            ))
+            .id();
+
+        begin(&mut world, "Delete", [video, audio]);
+        world.despawn(video);
+        world.despawn(audio);
+        commit(&mut world);
+
+        // Undo, redo and undo again respawn both twice.
+        for step in [undo, redo, undo] {
+            assert!(step(&mut world));
+        }
+        let video = history(&world).resolve(video);
+        let audio = history(&world).resolve(audio);
+        assert_eq!(span(&world, video), (0, 100));
+        assert_eq!(world.get::<BindTo>(audio).unwrap().id, video);
+    }
+
+    #[test]
+    fn oldest_edits_are_evicted_past_the_limit() {
+        let mut world = timeline();
+        let e = clip(&mut world, 0, 100);
+        let edit = |world: &mut World, start: u64| {
+            begin(world, "Move", [e]);
+            move_to(world, e, start, start + 100);
+            commit(world);
+        };
+
+        edit(&mut world, 10);
+        // Every edit below snapshots the same components, so costs the same.
+        let one = history(&world).bytes;
+        world.resource_mut::<EditHistory>().limit_bytes = 2 * one;
+        edit(&mut world, 20);
+        edit(&mut world, 30);
+
+        assert_eq!(history(&world).bytes, 2 * one);
+        assert!(undo(&mut world));
+        assert!(undo(&mut world));
+        assert!(!undo(&mut world));
+        assert_eq!(span(&world, e), (10, 110));
+
+        // The latest edit stays even when it alone is over the limit.
+        world.resource_mut::<EditHistory>().limit_bytes = 0;
+        edit(&mut world, 40);
+        assert!(history(&world).can_undo());
+        assert!(!history(&world).can_redo());
+    }
+
+    #[test]
+    fn reset_keeps_history_only_when_asked() {
+        let mut world = timeline();
+        let e = clip(&mut world, 0, 100);
+        begin(&mut world, "Move", [e]);
+        move_to(&mut world, e, 50, 150);
+        commit(&mut world);
+
+        let mut history = world.resource_mut::<EditHistory>();
+        history.keep_on_reset = true;
+        history.reset();
+        assert!(history.can_undo());
+
+        history.keep_on_reset = false;
+        history.limit_bytes = 1024;
+        history.reset();
+        assert!(!history.can_undo());
+        assert_eq!(history.limit_bytes, 1024);
+    }
+}
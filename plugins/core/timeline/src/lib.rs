@@ -7,8 +7,13 @@ use lunaris_api::{
 use lunaris_ecs::prelude::*;
 use std::collections::HashSet;
 
-mod components;
-use components::TimelineElement;
+pub mod components;
+pub mod edit;
+pub mod history;
+pub mod snap;
+use components::{BindTo, Marker, MediaState, TimelineElement};
+use edit::{ClipState, Edge};
+use snap::{SnapKind, SnapTarget};
 
 export_plugin!(Timeline, id: "lunaris.core.timeline", name: "Timeline", [Gui]);
 
@@ -25,8 +30,152 @@ pub struct TimelineUiState {
     track_gap: f32,
     playhead_tick: u64,
     selection: HashSet<Entity>,
+    drag: Option<Drag>,
+    tool: Tool,
+    snapping: bool,
+    /// Tick the dragged edges are snapped to, shown while dragging.
+    snapped: Option<u64>,
 }
 
+/// Pointer gesture in progress on the canvas.
+#[derive(Clone)]
+enum Drag {
+    /// Moving the selected clips, captured where they were when the drag began.
+    Move {
+        origin: egui::Pos2,
+        clips: Vec<ClipState>,
+    },
+    Trim {
+        origin: egui::Pos2,
+        edge: Edge,
+        clip: ClipState,
+    },
+    /// Trimming `clip` while the clips after it on its track follow.
+    Ripple {
+        origin: egui::Pos2,
+        edge: Edge,
+        clip: ClipState,
+        following: Vec<ClipState>,
+    },
+    /// Moving the cut between two adjacent clips.
+    Roll {
+        origin: egui::Pos2,
+        left: ClipState,
+        right: ClipState,
+    },
+    Slip {
+        origin: egui::Pos2,
+        clip: ClipState,
+    },
+    /// Moving `clip` between its neighbours, which give and take the room.
+    Slide {
+        origin: egui::Pos2,
+        clip: ClipState,
+        left: Option<ClipState>,
+        right: Option<ClipState>,
+    },
+    /// Rubber-band selection added to `base`.
+    Marquee {
+        origin: egui::Pos2,
+        base: HashSet<Entity>,
+    },
+}
+
+impl Drag {
+    /// Undo label and the clips the gesture can change, `None` for selection.
+    fn edit(&self) -> Option<(&'static str, Vec<Entity>)> {
+        let ids =
+            |clips: &[&ClipState]| -> Vec<Entity> { clips.iter().map(|c| c.entity).collect() };
+        Some(match self {
+            Drag::Move { clips, .. } => ("Move", ids(&clips.iter().collect::<Vec<_>>())),
+            Drag::Trim { clip, .. } => ("Trim", ids(&[clip])),
+            Drag::Ripple {
+                clip, following, ..
+            } => (
+                "Ripple trim",
+                following.iter().chain([clip]).map(|c| c.entity).collect(),
+            ),
+            Drag::Roll { left, right, .. } => ("Roll", ids(&[left, right])),
+            Drag::Slip { clip, .. } => ("Slip", ids(&[clip])),
+            Drag::Slide {
+                clip, left, right, ..
+            } => (
+                "Slide",
+                [Some(clip), left.as_ref(), right.as_ref()]
+                    .into_iter()
+                    .flatten()
+                    .map(|c| c.entity)
+                    .collect(),
+            ),
+            Drag::Marquee { .. } => return None,
+        })
+    }
+
+    fn origin(&self) -> egui::Pos2 {
+        match self {
+            Drag::Move { origin, .. }
+            | Drag::Trim { origin, .. }
+            | Drag::Ripple { origin, .. }
+            | Drag::Roll { origin, .. }
+            | Drag::Slip { origin, .. }
+            | Drag::Slide { origin, .. }
+            | Drag::Marquee { origin, .. } => *origin,
+        }
+    }
+}
+
+/// What dragging a clip does. Edge drags trim with `Select`, ripple trim with
+/// `Ripple` and roll with `Roll`; body drags move the selection except with
+/// `Slip` and `Slide`, which act on the clip under the pointer. `Razor` cuts
+/// the clicked clip at the pointer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tool {
+    Select,
+    Ripple,
+    Roll,
+    Slip,
+    Slide,
+    Razor,
+}
+
+impl Tool {
+    const ALL: [Tool; 6] = [
+        Tool::Select,
+        Tool::Ripple,
+        Tool::Roll,
+        Tool::Slip,
+        Tool::Slide,
+        Tool::Razor,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Tool::Select => "Select",
+            Tool::Ripple => "Ripple",
+            Tool::Roll => "Roll",
+            Tool::Slip => "Slip",
+            Tool::Slide => "Slide",
+            Tool::Razor => "Razor",
+        }
+    }
+
+    fn key(self) -> egui::Key {
+        match self {
+            Tool::Select => egui::Key::V,
+            Tool::Ripple => egui::Key::B,
+            Tool::Roll => egui::Key::N,
+            Tool::Slip => egui::Key::Y,
+            Tool::Slide => egui::Key::U,
+            Tool::Razor => egui::Key::C,
+        }
+    }
+}
+
+/// How close to a clip edge, in pixels, a drag trims instead of moving.
+const EDGE_GRAB_PX: f32 = 6.0;
+/// How close, in pixels, a dragged edge has to come to something to snap to it.
+const SNAP_PX: f32 = 8.0;
+
 impl Default for TimelineUiState {
     fn default() -> Self {
         Self {
@@ -38,6 +187,10 @@ impl Default for TimelineUiState {
             track_gap: 6.0,
             playhead_tick: 0,
             selection: HashSet::new(),
+            drag: None,
+            tool: Tool::Select,
+            snapping: true,
+            snapped: None,
         }
     }
 }
@@ -48,6 +201,8 @@ impl Plugin for Timeline {
             .insert_resource(lunaris_api::plugin::UiContext::new_clonable(
                 TimelineUiState::default(),
             ));
+        ctx.world.init_resource::<history::EditHistory>();
+        history::track_timeline(ctx.world);
         Ok(())
     }
 
@@ -55,7 +210,11 @@ impl Plugin for Timeline {
         Ok(())
     }
 
-    fn reset(&mut self, _ctx: PluginContext<'_>) {}
+    fn reset(&mut self, ctx: PluginContext<'_>) {
+        if let Some(mut history) = ctx.world.get_resource_mut::<history::EditHistory>() {
+            history.reset();
+        }
+    }
 
     fn report(&self, _ctx: PluginContext<'_>) -> PluginReport {
         PluginReport::Operational
@@ -162,6 +321,7 @@ impl Gui for Timeline {
                 st.ticks_per_px,
                 self.tick_freq,
             );
+            draw_markers(&p, top_ruler, &st, ctx.world);
         }
 
         // Gutter: tracks (vertical scroll only)
@@ -176,10 +336,31 @@ impl Gui for Timeline {
             );
         }
 
+        // Keyboard: tools, split and delete, while the pointer is over the timeline
+        if resp_outer.hovered() && !ui.ctx().wants_keyboard_input() {
+            handle_keys(ui, &mut st, ctx.world);
+        }
+
+        // Canvas: select and edit clips with the current tool
+        edit_clips(ui, &resp_outer, canvas, &mut st, ctx.world, self.tick_freq);
+
         // Canvas: clips (both scroll axes)
         {
             let p = ui.painter_at(canvas);
             draw_clips(&p, canvas, &st, ctx.world);
+            if let Some(Drag::Marquee { origin, .. }) = &st.drag
+                && let Some(pos) = resp_outer.interact_pointer_pos()
+            {
+                let r = egui::Rect::from_two_pos(*origin, pos);
+                let col = ui.visuals().selection.bg_fill;
+                p.rect_filled(r, 0.0, col.linear_multiply(0.25));
+                p.rect_stroke(
+                    r,
+                    0.0,
+                    egui::Stroke::new(1.0, col),
+                    egui::StrokeKind::Inside,
+                );
+            }
         }
 
         // Playhead over ruler+canvas
@@ -193,6 +374,18 @@ impl Gui for Timeline {
             egui::Stroke::new(2.0, egui::Color32::from_rgb(255, 64, 64)),
         );
 
+        // Snap indicator while dragging
+        if let Some(tick) = st.snapped {
+            let x = canvas.left() + ((tick as f64 - st.scroll_x_ticks) / st.ticks_per_px) as f32;
+            ui.painter().line_segment(
+                [
+                    egui::pos2(x, top_ruler.top()),
+                    egui::pos2(x, canvas.bottom()),
+                ],
+                egui::Stroke::new(1.0, egui::Color32::from_rgb(255, 210, 60)),
+            );
+        }
+
         // Scrub on click in ruler
         if let Some(pos) = ui.ctx().pointer_latest_pos()
             && top_ruler.contains(pos)
@@ -213,6 +406,13 @@ impl Gui for Timeline {
         if resp.changed() {
             st.ticks_per_px = self.tick_freq as f64 / zoom.max(1.0) as f64;
         }
+        z_ui.checkbox(&mut st.snapping, "Snap")
+            .on_hover_text("Hold Alt while dragging to ignore snapping");
+        z_ui.separator();
+        for tool in Tool::ALL {
+            z_ui.selectable_value(&mut st.tool, tool, tool.label())
+                .on_hover_text(tool.key().name());
+        }
 
         // Save state back to World
         let ui_ctx =
@@ -308,6 +508,399 @@ fn draw_track_gutter(
     }
 }
 
+/// Handles primary-button clicks and drags on the canvas: click and marquee
+/// selection (Ctrl/Shift to add), razor cuts, and moving, trimming, ripple,
+/// roll, slip and slide edits depending on the tool. Dragged edges and cuts snap
+/// unless Alt is held.
+fn edit_clips(
+    ui: &egui::Ui,
+    resp: &egui::Response,
+    canvas: egui::Rect,
+    st: &mut TimelineUiState,
+    world: &mut World,
+    tps: u64,
+) {
+    let mods = ui.input(|i| i.modifiers);
+    let additive = mods.ctrl || mods.shift || mods.command;
+    let snapping = st.snapping && !mods.alt;
+
+    if resp.clicked_by(egui::PointerButton::Primary)
+        && let Some(pos) = resp.interact_pointer_pos()
+        && canvas.contains(pos)
+        && st.tool == Tool::Razor
+    {
+        if let Some((ent, _)) = clip_at(world, st, canvas, pos) {
+            let mut tick = (st.scroll_x_ticks + (pos.x - canvas.left()) as f64 * st.ticks_per_px)
+                .max(0.0)
+                .round() as u64;
+            if snapping {
+                let (d, _) = snap_delta(world, st, &[ent], &[tick], 0, tps);
+                tick = tick.saturating_add_signed(d);
+            }
+            history::begin(world, "Split", [ent]);
+            let added = edit::split(world, &[ent], tick);
+            history::created(world, added);
+            history::commit(world);
+        }
+    } else if resp.clicked_by(egui::PointerButton::Primary)
+        && let Some(pos) = resp.interact_pointer_pos()
+        && canvas.contains(pos)
+    {
+        match clip_at(world, st, canvas, pos) {
+            Some((ent, _)) if additive => {
+                if !st.selection.remove(&ent) {
+                    st.selection.insert(ent);
+                }
+            }
+            Some((ent, _)) => st.selection = HashSet::from([ent]),
+            None if additive => {}
+            None => st.selection.clear(),
+        }
+    }
+
+    if resp.drag_started_by(egui::PointerButton::Primary)
+        && let Some(origin) = ui.input(|i| i.pointer.press_origin())
+        && canvas.contains(origin)
+    {
+        st.drag = match clip_at(world, st, canvas, origin) {
+            None => Some(Drag::Marquee {
+                origin,
+                base: if additive {
+                    st.selection.clone()
+                } else {
+                    HashSet::new()
+                },
+            }),
+            Some((ent, edge)) => {
+                if !st.selection.contains(&ent) {
+                    if !additive {
+                        st.selection.clear();
+                    }
+                    st.selection.insert(ent);
+                }
+                start_drag(world, st, ent, edge, origin)
+            }
+        };
+        if let Some((label, clips)) = st.drag.as_ref().and_then(Drag::edit) {
+            history::begin(world, label, clips);
+        }
+    }
+
+    if let Some(drag) = &st.drag
+        && resp.dragged_by(egui::PointerButton::Primary)
+        && let Some(pos) = resp.interact_pointer_pos()
+    {
+        let origin = drag.origin();
+        let delta = ((pos.x - origin.x) as f64 * st.ticks_per_px).round() as i64;
+        let snap = |world: &mut World, dragged: &[&ClipState], edges: &[u64]| {
+            if !snapping {
+                return (delta, None);
+            }
+            let dragged: Vec<Entity> = dragged.iter().map(|c| c.entity).collect();
+            snap_delta(world, st, &dragged, edges, delta, tps)
+        };
+        let (wanted, snapped, applied) = match drag {
+            Drag::Move { clips, .. } => {
+                let tracks = ((pos.y - origin.y) / (st.track_height + st.track_gap)).round();
+                let edges: Vec<u64> = clips
+                    .iter()
+                    .flat_map(|c| [c.position.start, c.position.end])
+                    .collect();
+                let (d, snapped) = snap(world, &clips.iter().collect::<Vec<_>>(), &edges);
+                let (applied, _) = edit::move_clips(world, clips, d, tracks as i64);
+                (d, snapped, applied)
+            }
+            Drag::Trim { edge, clip, .. } => {
+                let (d, snapped) = snap(world, &[clip], &[clip.edge(*edge)]);
+                (d, snapped, edit::trim_clip(world, clip, *edge, d))
+            }
+            Drag::Ripple {
+                edge,
+                clip,
+                following,
+                ..
+            } => {
+                let mut dragged: Vec<&ClipState> = following.iter().collect();
+                dragged.push(clip);
+                let (d, snapped) = snap(world, &dragged, &[clip.edge(*edge)]);
+                (
+                    d,
+                    snapped,
+                    edit::ripple_trim(world, clip, following, *edge, d),
+                )
+            }
+            Drag::Roll { left, right, .. } => {
+                let (d, snapped) = snap(world, &[left, right], &[left.position.end]);
+                (d, snapped, edit::roll(world, left, right, d))
+            }
+            Drag::Slip { clip, .. } => {
+                // Dragging right pulls earlier material into view.
+                (-delta, None, edit::slip(world, clip, -delta))
+            }
+            Drag::Slide {
+                clip, left, right, ..
+            } => {
+                let dragged: Vec<&ClipState> = [Some(clip), left.as_ref(), right.as_ref()]
+                    .into_iter()
+                    .flatten()
+                    .collect();
+                let (d, snapped) = snap(world, &dragged, &[clip.position.start, clip.position.end]);
+                (
+                    d,
+                    snapped,
+                    edit::slide(world, clip, left.as_ref(), right.as_ref(), d),
+                )
+            }
+            Drag::Marquee { base, .. } => {
+                let area = egui::Rect::from_two_pos(origin, pos);
+                let mut selection = base.clone();
+                let mut q = world.query::<(Entity, &TimelineElement)>();
+                for (ent, el) in q.iter(world) {
+                    if clip_rect(canvas, st, el).intersects(area) {
+                        selection.insert(ent);
+                    }
+                }
+                st.selection = selection;
+                (0, None, 0)
+            }
+        };
+        st.snapped = snapped.filter(|_| applied == wanted);
+    }
+
+    if resp.drag_stopped() {
+        st.drag = None;
+        st.snapped = None;
+        history::commit(world);
+    }
+
+    let trimming = matches!(
+        st.drag,
+        Some(Drag::Trim { .. } | Drag::Ripple { .. } | Drag::Roll { .. })
+    );
+    let over_edge = st.drag.is_none()
+        && matches!(st.tool, Tool::Select | Tool::Ripple | Tool::Roll)
+        && resp
+            .hover_pos()
+            .filter(|pos| canvas.contains(*pos))
+            .and_then(|pos| clip_at(world, st, canvas, pos))
+            .is_some_and(|(_, edge)| edge.is_some());
+    if trimming || over_edge {
+        ui.ctx().set_cursor_icon(egui::CursorIcon::ResizeHorizontal);
+    } else if st.tool == Tool::Razor
+        && resp
+            .hover_pos()
+            .filter(|pos| canvas.contains(*pos))
+            .and_then(|pos| clip_at(world, st, canvas, pos))
+            .is_some()
+    {
+        ui.ctx().set_cursor_icon(egui::CursorIcon::Crosshair);
+    }
+}
+
+/// Tool shortcuts, Ctrl+Z / Ctrl+Shift+Z to undo and redo, Ctrl+K to split the
+/// selected clips (or every clip) at the playhead, and Delete / Shift+Delete to
+/// remove or ripple delete the selected clips.
+fn handle_keys(ui: &egui::Ui, st: &mut TimelineUiState, world: &mut World) {
+    let (tool, undo, redo, split, delete, mods) = ui.input(|i| {
+        let z = i.modifiers.command && i.key_pressed(egui::Key::Z);
+        (
+            Tool::ALL.into_iter().find(|t| i.key_pressed(t.key())),
+            z && !i.modifiers.shift,
+            (z && i.modifiers.shift) || (i.modifiers.command && i.key_pressed(egui::Key::Y)),
+            i.modifiers.command && i.key_pressed(egui::Key::K),
+            i.key_pressed(egui::Key::Delete) || i.key_pressed(egui::Key::Backspace),
+            i.modifiers,
+        )
+    });
+    if (undo && history::undo(world)) || (redo && history::redo(world)) {
+        // Deleted clips come back as new entities.
+        let history = world.resource::<history::EditHistory>();
+        let selection: Vec<Entity> = st.selection.iter().map(|&e| history.resolve(e)).collect();
+        st.selection = selection
+            .into_iter()
+            .filter(|&e| world.get::<TimelineElement>(e).is_some())
+            .collect();
+    }
+    if split && st.drag.is_none() {
+        let targets: Vec<Entity> = if st.selection.is_empty() {
+            let mut q = world.query_filtered::<Entity, With<TimelineElement>>();
+            q.iter(world).collect()
+        } else {
+            st.selection.iter().copied().collect()
+        };
+        history::begin(world, "Split", targets.iter().copied());
+        let added = edit::split(world, &targets, st.playhead_tick);
+        history::created(world, added);
+        history::commit(world);
+    }
+    if let Some(tool) = tool
+        && mods.is_none()
+    {
+        st.tool = tool;
+    }
+    if delete && st.drag.is_none() && !st.selection.is_empty() {
+        let selected: Vec<Entity> = st.selection.drain().collect();
+        if mods.shift {
+            let following = selected
+                .iter()
+                .filter_map(|&e| ClipState::capture(world, e))
+                .flat_map(|c| c.following(world))
+                .map(|c| c.entity)
+                .collect::<Vec<_>>();
+            let touched = selected.iter().chain(&following).copied();
+            history::begin(world, "Ripple delete", touched);
+            edit::ripple_delete(world, &selected);
+        } else {
+            history::begin(world, "Delete", selected.iter().copied());
+            for ent in selected {
+                world.despawn(ent);
+            }
+        }
+        history::commit(world);
+    }
+}
+
+/// Picks the gesture for a drag that starts on clip `ent`, grabbed by `edge`
+/// if near one, with the current tool.
+fn start_drag(
+    world: &mut World,
+    st: &TimelineUiState,
+    ent: Entity,
+    edge: Option<Edge>,
+    origin: egui::Pos2,
+) -> Option<Drag> {
+    let clip = ClipState::capture(world, ent)?;
+    let drag = match (st.tool, edge) {
+        (Tool::Razor, _) => return None,
+        (Tool::Slip, _) => Drag::Slip { origin, clip },
+        (Tool::Slide, _) => {
+            let (left, right) = clip.neighbours(world);
+            Drag::Slide {
+                origin,
+                clip,
+                left,
+                right,
+            }
+        }
+        (Tool::Ripple, Some(edge)) => Drag::Ripple {
+            origin,
+            edge,
+            following: clip.following(world),
+            clip,
+        },
+        (Tool::Roll, Some(edge)) => {
+            // Without a clip on the other side of the cut, roll is a plain trim.
+            let (left, right) = clip.neighbours(world);
+            match (edge, left, right) {
+                (Edge::Start, Some(left), _) => Drag::Roll {
+                    origin,
+                    left,
+                    right: clip,
+                },
+                (Edge::End, _, Some(right)) => Drag::Roll {
+                    origin,
+                    left: clip,
+                    right,
+                },
+                _ => Drag::Trim { origin, edge, clip },
+            }
+        }
+        (_, Some(edge)) => Drag::Trim { origin, edge, clip },
+        (_, None) => Drag::Move {
+            origin,
+            clips: st
+                .selection
+                .iter()
+                .filter_map(|&e| ClipState::capture(world, e))
+                .collect(),
+        },
+    };
+    Some(drag)
+}
+
+/// Adds to `delta` the correction that snaps one of `edges`, moved by `delta`,
+/// to the playhead, a marker, a grid line or an edge of a clip not in
+/// `dragged`. Returns the corrected delta and the tick snapped to.
+fn snap_delta(
+    world: &mut World,
+    st: &TimelineUiState,
+    dragged: &[Entity],
+    edges: &[u64],
+    delta: i64,
+    tps: u64,
+) -> (i64, Option<u64>) {
+    let mut targets = vec![SnapTarget {
+        tick: st.playhead_tick,
+        kind: SnapKind::Playhead,
+    }];
+    let mut q = world.query::<(Entity, &TimelineElement)>();
+    for (ent, el) in q.iter(world) {
+        if dragged.contains(&ent) {
+            continue;
+        }
+        for tick in [el.position.start, el.position.end] {
+            targets.push(SnapTarget {
+                tick,
+                kind: SnapKind::ClipEdge,
+            });
+        }
+    }
+    let mut q = world.query::<&Marker>();
+    targets.extend(q.iter(world).map(|m| SnapTarget {
+        tick: m.tick,
+        kind: SnapKind::Marker,
+    }));
+    let moved: Vec<u64> = edges
+        .iter()
+        .map(|t| t.saturating_add_signed(delta))
+        .collect();
+    match snap::snap(
+        &moved,
+        &targets,
+        Some(choose_grid_step(st.ticks_per_px, tps)),
+        snap::threshold_ticks(SNAP_PX, st.ticks_per_px),
+    ) {
+        Some(s) => (delta + s.delta, Some(s.target.tick)),
+        None => (delta, None),
+    }
+}
+
+/// Topmost clip under `pos`, and the edge it is grabbed by if `pos` is close
+/// to one.
+fn clip_at(
+    world: &mut World,
+    st: &TimelineUiState,
+    canvas: egui::Rect,
+    pos: egui::Pos2,
+) -> Option<(Entity, Option<Edge>)> {
+    let mut q = world.query::<(Entity, &TimelineElement)>();
+    let (ent, r) = q
+        .iter(world)
+        .map(|(ent, el)| (ent, clip_rect(canvas, st, el)))
+        .filter(|(_, r)| r.contains(pos))
+        .last()?;
+    let grab = EDGE_GRAB_PX.min(r.width() / 3.0);
+    let edge = if pos.x - r.left() <= grab {
+        Some(Edge::Start)
+    } else if r.right() - pos.x <= grab {
+        Some(Edge::End)
+    } else {
+        None
+    };
+    Some((ent, edge))
+}
+
+/// Screen rectangle of a clip on the canvas.
+fn clip_rect(rect: egui::Rect, st: &TimelineUiState, el: &TimelineElement) -> egui::Rect {
+    let x0 =
+        rect.left() + ((el.position.start as f64 - st.scroll_x_ticks) / st.ticks_per_px) as f32;
+    let x1 = rect.left() + ((el.position.end as f64 - st.scroll_x_ticks) / st.ticks_per_px) as f32;
+    let y0 =
+        rect.top() + ((el.track_num as f32) * (st.track_height + st.track_gap) - st.scroll_y_px);
+    let y1 = y0 + st.track_height;
+    egui::Rect::from_min_max(egui::pos2(x0, y0), egui::pos2(x1, y1))
+}
+
 fn draw_clips(p: &egui::Painter, rect: egui::Rect, st: &TimelineUiState, world: &mut World) {
     let start_tick = st.scroll_x_ticks.max(0.0) as u64;
     let end_tick = (st.scroll_x_ticks + rect.width() as f64 * st.ticks_per_px) as u64;
@@ -316,21 +909,34 @@ fn draw_clips(p: &egui::Painter, rect: egui::Rect, st: &TimelineUiState, world:
         if el.position.end < start_tick || el.position.start > end_tick {
             continue;
         }
-        let x0 =
-            rect.left() + ((el.position.start as f64 - st.scroll_x_ticks) / st.ticks_per_px) as f32;
-        let x1 =
-            rect.left() + ((el.position.end as f64 - st.scroll_x_ticks) / st.ticks_per_px) as f32;
-        let y0 = rect.top()
-            + ((el.track_num as f32) * (st.track_height + st.track_gap) - st.scroll_y_px);
-        let y1 = y0 + st.track_height;
-        let clip = egui::Rect::from_min_max(egui::pos2(x0, y0), egui::pos2(x1, y1));
+        let clip = clip_rect(rect, st, el);
         let sel = st.selection.contains(&ent);
-        let fill = if sel {
-            egui::Color32::from_rgb(80, 120, 220)
-        } else {
-            p.ctx().style().visuals.widgets.inactive.bg_fill
+        // Media state lives on the source, which is the clip or what it binds to.
+        let source = world.get::<BindTo>(ent).map_or(ent, |b| b.id);
+        let problem = match world.get::<MediaState>(source) {
+            Some(MediaState::Offline { .. }) => {
+                Some(("Media offline", egui::Color32::from_rgb(170, 40, 40)))
+            }
+            Some(MediaState::Unsupported { .. }) => {
+                Some(("Unsupported media", egui::Color32::from_rgb(190, 120, 30)))
+            }
+            _ => None,
+        };
+        let fill = match problem {
+            Some((_, color)) => color,
+            None if sel => egui::Color32::from_rgb(80, 120, 220),
+            None => p.ctx().style().visuals.widgets.inactive.bg_fill,
         };
         p.rect_filled(clip, 3.0, fill);
+        if let Some((label, _)) = problem {
+            p.text(
+                clip.left_center() + egui::vec2(4.0, 0.0),
+                egui::Align2::LEFT_CENTER,
+                label,
+                egui::FontId::proportional(12.0),
+                egui::Color32::WHITE,
+            );
+        }
         p.rect_stroke(
             clip,
             3.0,
@@ -349,6 +955,36 @@ fn draw_clips(p: &egui::Painter, rect: egui::Rect, st: &TimelineUiState, world:
     }
 }
 
+fn draw_markers(p: &egui::Painter, rect: egui::Rect, st: &TimelineUiState, world: &mut World) {
+    let col = egui::Color32::from_rgb(90, 200, 120);
+    let mut q = world.query::<&Marker>();
+    for marker in q.iter(world) {
+        let x = rect.left() + ((marker.tick as f64 - st.scroll_x_ticks) / st.ticks_per_px) as f32;
+        if x < rect.left() - 5.0 || x > rect.right() + 5.0 {
+            continue;
+        }
+        let tip = egui::pos2(x, rect.bottom());
+        p.add(egui::Shape::convex_polygon(
+            vec![
+                tip,
+                tip + egui::vec2(-5.0, -8.0),
+                tip + egui::vec2(5.0, -8.0),
+            ],
+            col,
+            egui::Stroke::NONE,
+        ));
+        if !marker.label.is_empty() {
+            p.text(
+                tip + egui::vec2(7.0, -1.0),
+                egui::Align2::LEFT_BOTTOM,
+                &marker.label,
+                egui::FontId::proportional(11.0),
+                col,
+            );
+        }
+    }
+}
+
 fn choose_grid_step(ticks_per_px: f64, tps: u64) -> u64 {
     let target_px = 60.0;
     let candidates_ms = [1, 2, 5, 10, 20, 50, 100, 200, 500, 1000, 2000, 5000, 10_000];
@@ -0,0 +1,156 @@
+//! Snapping maths, kept free of egui and the ECS so it can be reasoned about
+//! on plain numbers.
+
+/// What an edge snapped to, in order of preference when two are equally close.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SnapKind {
+    ClipEdge,
+    Playhead,
+    Marker,
+    Grid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnapTarget {
+    pub tick: u64,
+    pub kind: SnapKind,
+}
+
+/// Correction found by [`snap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Snap {
+    /// Ticks to add to the dragged edges.
+    pub delta: i64,
+    pub target: SnapTarget,
+}
+
+/// Finds the smallest shift that puts one of `edges` on a target no more than
+/// `threshold` ticks away.
+///
+/// `targets` are checked as given; grid lines, every `grid_step` ticks from 0,
+/// are checked around each edge instead of being listed. Ties go to the
+/// closest edge first, then to the preferred [`SnapKind`].
+pub fn snap(
+    edges: &[u64],
+    targets: &[SnapTarget],
+    grid_step: Option<u64>,
+    threshold: u64,
+) -> Option<Snap> {
+    let mut best: Option<(u64, Snap)> = None;
+    let mut consider = |edge: u64, target: SnapTarget| {
+        let distance = edge.abs_diff(target.tick);
+        if distance > threshold {
+            return;
+        }
+        let better = best.is_none_or(|(d, b)| (distance, target.kind) < (d, b.target.kind));
+        if better {
+            let delta = target.tick as i64 - edge as i64;
+            best = Some((distance, Snap { delta, target }));
+        }
+    };
+    for &edge in edges {
+        for &target in targets {
+            consider(edge, target);
+        }
+        if let Some(step) = grid_step.filter(|&s| s > 0) {
+            let below = edge / step * step;
+            for tick in [below, below.saturating_add(step)] {
+                consider(
+                    edge,
+                    SnapTarget {
+                        tick,
+                        kind: SnapKind::Grid,
+                    },
+                );
+            }
+        }
+    }
+    best.map(|(_, snap)| snap)
+}
+
+/// Snap distance in ticks for a pixel threshold at the current zoom.
+pub fn threshold_ticks(threshold_px: f32, ticks_per_px: f64) -> u64 {
+    (threshold_px as f64 * ticks_per_px).round().max(0.0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(tick: u64, kind: SnapKind) -> SnapTarget {
+        SnapTarget { tick, kind }
+    }
+
+    #[test]
+    fn snaps_at_the_threshold_but_not_past_it() {
+        let targets = [target(110, SnapKind::Playhead)];
+        let snapped = snap(&[100], &targets, None, 10).unwrap();
+        assert_eq!(snapped.delta, 10);
+        assert_eq!(snapped.target, targets[0]);
+        assert_eq!(snap(&[100], &targets, None, 9), None);
+        assert_eq!(snap(&[120], &targets, None, 10).unwrap().delta, -10);
+    }
+
+    #[test]
+    fn equal_distances_go_to_the_preferred_kind() {
+        let targets = [
+            target(105, SnapKind::Marker),
+            target(105, SnapKind::Playhead),
+            target(95, SnapKind::ClipEdge),
+        ];
+        let snapped = snap(&[100], &targets, None, 10).unwrap();
+        assert_eq!(snapped.delta, -5);
+        assert_eq!(snapped.target.kind, SnapKind::ClipEdge);
+
+        // Grid lines at 90 and 100 are as close as the marker.
+        let snapped = snap(&[95], &[target(100, SnapKind::Marker)], Some(10), 10).unwrap();
+        assert_eq!(snapped.delta, 5);
+        assert_eq!(snapped.target.kind, SnapKind::Marker);
+    }
+
+    #[test]
+    fn snaps_to_the_grid_line_below_or_above() {
+        let below = snap(&[230], &[], Some(100), 40).unwrap();
+        assert_eq!(below.delta, -30);
+        assert_eq!(below.target, target(200, SnapKind::Grid));
+
+        let above = snap(&[280], &[], Some(100), 40).unwrap();
+        assert_eq!(above.delta, 20);
+        assert_eq!(above.target, target(300, SnapKind::Grid));
+
+        assert_eq!(snap(&[250], &[], Some(100), 40), None);
+    }
+
+    #[test]
+    fn zero_grid_step_disables_the_grid() {
+        assert_eq!(snap(&[5], &[], Some(0), 100), None);
+        let targets = [target(8, SnapKind::Marker)];
+        assert_eq!(snap(&[5], &targets, Some(0), 100).unwrap().delta, 3);
+    }
+
+    #[test]
+    fn picks_the_closest_of_several_edges() {
+        let targets = [
+            target(260, SnapKind::ClipEdge),
+            target(108, SnapKind::Playhead),
+        ];
+        let snapped = snap(&[100, 250], &targets, None, 20).unwrap();
+        assert_eq!(snapped.delta, 8);
+        assert_eq!(snapped.target.kind, SnapKind::Playhead);
+
+        // Same distance from different edges: the kind decides.
+        let targets = [
+            target(258, SnapKind::ClipEdge),
+            target(108, SnapKind::Playhead),
+        ];
+        let snapped = snap(&[100, 250], &targets, None, 20).unwrap();
+        assert_eq!(snapped.delta, 8);
+        assert_eq!(snapped.target.kind, SnapKind::ClipEdge);
+    }
+
+    #[test]
+    fn threshold_scales_with_zoom() {
+        assert_eq!(threshold_ticks(8.0, 2.5), 20);
+        assert_eq!(threshold_ticks(-4.0, 2.5), 0);
+    }
+}
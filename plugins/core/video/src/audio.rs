@@ -0,0 +1,495 @@
+//! Audio decode path: PCM for a tick range, resampled to the requested format.
+
+#[cfg(feature = "real_ffmpeg")]
+use crate::error::MediaError;
+use crate::{error::MediaResult, params};
+#[cfg(feature = "real_ffmpeg")]
+use ffmpeg_next as ffmpeg;
+use lunaris_api::{
+    consts::tps,
+    plugin::RenderJob,
+    util::error::{LunarisError, Result},
+};
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+/// Interleaved `f32` samples covering `start..start + frames()` worth of ticks.
+#[derive(Debug, Clone)]
+pub struct AudioBuffer {
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Source time, in ticks, of the first sample.
+    pub start: u64,
+    pub samples: Vec<f32>,
+}
+
+impl AudioBuffer {
+    /// Number of sample frames (samples per channel).
+    pub fn frames(&self) -> usize {
+        self.samples.len() / usize::from(self.channels.max(1))
+    }
+}
+
+/// An audio render request. Built from a `RenderJob` with `kind = "audio"`.
+#[derive(Debug, Clone)]
+pub struct AudioJob {
+    pub path: String,
+    /// Source time range in ticks, end exclusive.
+    pub start: u64,
+    pub end: u64,
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+impl AudioJob {
+    pub const DEFAULT_SAMPLE_RATE: u32 = 48_000;
+    pub const DEFAULT_CHANNELS: u16 = 2;
+
+    /// Reads `path`, `start_tick`/`end_tick` (timeline ticks, mapped into the
+    /// clip like video jobs) and optional `sample_rate`/`channels`.
+    pub fn from_render_job(job: &RenderJob) -> Result<Self> {
+        let path = params::path(job)?;
+        let start = params::uint(job, "start_tick")?.ok_or_else(|| missing("start_tick"))?;
+        let end = params::uint(job, "end_tick")?.ok_or_else(|| missing("end_tick"))?;
+        if end <= start {
+            return Err(LunarisError::InvalidArgument {
+                name: "end_tick".to_string(),
+                reason: Some("Audio range must not be empty".to_string()),
+            });
+        }
+        if params::float(job, "speed")?.is_some_and(|s| s != 1.0) {
+            return Err(LunarisError::InvalidArgument {
+                name: "speed".to_string(),
+                reason: Some("Retimed audio is not supported yet".to_string()),
+            });
+        }
+        let source_start = crate::source_tick(job, start)?;
+        let sample_rate = params::uint(job, "sample_rate")?
+            .map(|r| r.clamp(1, 768_000) as u32)
+            .unwrap_or(Self::DEFAULT_SAMPLE_RATE);
+        let channels = params::uint(job, "channels")?
+            .map(|c| c.clamp(1, 32) as u16)
+            .unwrap_or(Self::DEFAULT_CHANNELS);
+        Ok(Self {
+            path,
+            start: source_start,
+            end: source_start + (end - start),
+            sample_rate,
+            channels,
+        })
+    }
+
+    /// Sample frames covering the range. Samples are counted from source
+    /// time 0, so back-to-back jobs split them without a gap or overlap.
+    pub fn frames(&self) -> usize {
+        ticks_to_samples(self.end, self.sample_rate)
+            - ticks_to_samples(self.start, self.sample_rate)
+    }
+}
+
+fn missing(name: &str) -> LunarisError {
+    LunarisError::InvalidArgument {
+        name: name.to_string(),
+        reason: Some(format!("Missing '{name}' property for audio render job")),
+    }
+}
+
+fn ticks_to_samples(ticks: u64, sample_rate: u32) -> usize {
+    (u128::from(ticks) * u128::from(sample_rate) / u128::from(tps())) as usize
+}
+
+#[cfg(feature = "real_ffmpeg")]
+pub struct AudioDecoder {
+    path: String,
+    input: ffmpeg::format::context::Input,
+    decoder: ffmpeg::decoder::Audio,
+    stream_index: usize,
+    time_base: crate::time::Rational,
+    start_pts: i64,
+    // Kept while jobs follow on from each other; dropped on every seek so
+    // samples buffered from the old position never leak into the new range.
+    resampler: Option<(u32, u16, ffmpeg::software::resampling::Context)>,
+    /// Where the last job ended, with its rate and channels. A job starting
+    /// there continues decoding instead of seeking.
+    cursor: Option<(u64, u32, u16)>,
+    /// Samples resampled past the end of the last job, which start the next.
+    carry: Vec<f32>,
+}
+
+#[cfg(not(feature = "real_ffmpeg"))]
+pub struct AudioDecoder {}
+
+// Send is needed because we move AudioDecoder between threads (Orchestrator workers)
+unsafe impl Send for AudioDecoder {}
+
+impl AudioDecoder {
+    pub fn new(path: &Path) -> MediaResult<Self> {
+        #[cfg(feature = "real_ffmpeg")]
+        {
+            let path = path.display().to_string();
+            let input =
+                ffmpeg::format::input(&path).map_err(|e| MediaError::from_ffmpeg(&path, e))?;
+            let stream = input
+                .streams()
+                .best(ffmpeg::media::Type::Audio)
+                .ok_or_else(|| MediaError::Unsupported {
+                    path: path.clone(),
+                    reason: "No audio stream found".to_string(),
+                })?;
+            let stream_index = stream.index();
+            let time_base = crate::time::Rational::new(
+                i64::from(stream.time_base().numerator()),
+                i64::from(stream.time_base().denominator()),
+            );
+            let start_pts = match stream.start_time() {
+                ffmpeg::ffi::AV_NOPTS_VALUE => 0,
+                t => t,
+            };
+            let decoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters())
+                .and_then(|c| c.decoder().audio())
+                .map_err(|e| MediaError::from_ffmpeg(&path, e))?;
+            Ok(Self {
+                path,
+                input,
+                decoder,
+                stream_index,
+                time_base,
+                start_pts,
+                resampler: None,
+                cursor: None,
+                carry: Vec::new(),
+            })
+        }
+        #[cfg(not(feature = "real_ffmpeg"))]
+        {
+            let _ = path;
+            Ok(Self {})
+        }
+    }
+
+    /// Decodes `job.start..job.end` of source time into interleaved `f32`
+    /// at the job's rate and channel count. Gaps past the end of the stream
+    /// are filled with silence so the buffer always covers the whole range.
+    ///
+    /// A job starting where the previous one ended, at the same rate and
+    /// channel count, picks up the stream where it stopped; anything else
+    /// seeks.
+    pub fn decode_range(&mut self, job: &AudioJob) -> MediaResult<AudioBuffer> {
+        let channels = usize::from(job.channels);
+        let wanted = job.frames() * channels;
+
+        #[cfg(feature = "real_ffmpeg")]
+        let samples = {
+            use crate::time::{pts_to_ticks, ticks_to_pts};
+
+            let target = self.start_pts + ticks_to_pts(job.start, self.time_base);
+            // Cleared until this job succeeds, so a failed one seeks next time.
+            let cursor = self.cursor.take();
+            let mut out: Vec<f32> = Vec::with_capacity(wanted);
+            // Samples before `job.start` decoded from the seek point are dropped;
+            // `first` is where the collected data starts, in ticks.
+            let mut first: Option<u64> = None;
+            if cursor == Some((job.start, job.sample_rate, job.channels)) {
+                out.append(&mut self.carry);
+                first = Some(job.start);
+            } else {
+                let seek_us = (i128::from(target) * i128::from(self.time_base.num) * 1_000_000
+                    / i128::from(self.time_base.den)) as i64;
+                self.input
+                    .seek(seek_us, ..seek_us)
+                    .map_err(|e| MediaError::Other {
+                        path: self.path.clone(),
+                        reason: format!("Seek failed: {}", e),
+                    })?;
+                self.decoder.flush();
+                self.resampler = None;
+                self.carry.clear();
+            }
+            let mut skip = 0usize;
+            let stream_index = self.stream_index;
+            let mut draining = false;
+            let mut decoded = ffmpeg::util::frame::Audio::empty();
+            'decode: while out.len() < wanted {
+                while self.decoder.receive_frame(&mut decoded).is_ok() {
+                    if first.is_none() {
+                        let pts = decoded.timestamp().or(decoded.pts()).unwrap_or(target);
+                        let at = pts_to_ticks(pts - self.start_pts, self.time_base);
+                        first = Some(at);
+                        skip = ticks_to_samples(job.start.saturating_sub(at), job.sample_rate)
+                            * channels;
+                        // Audio starting after the requested point is preceded by silence.
+                        let lead = ticks_to_samples(at.saturating_sub(job.start), job.sample_rate)
+                            * channels;
+                        out.resize(lead.min(wanted), 0.0);
+                    }
+                    self.resample_into(&decoded, job, &mut out, &mut skip)?;
+                    if out.len() >= wanted {
+                        break 'decode;
+                    }
+                }
+                if draining {
+                    // The resampler holds back a few samples until it is flushed.
+                    self.flush_resampler(job, &mut out, &mut skip)?;
+                    break;
+                }
+                match self
+                    .input
+                    .packets()
+                    .find(|(stream, _)| stream.index() == stream_index)
+                {
+                    Some((_, packet)) => {
+                        self.decoder
+                            .send_packet(&packet)
+                            .map_err(|e| MediaError::from_ffmpeg(&self.path, e))?;
+                    }
+                    None => {
+                        let _ = self.decoder.send_eof();
+                        draining = true;
+                    }
+                }
+            }
+            if out.len() > wanted {
+                self.carry = out.split_off(wanted);
+            }
+            self.cursor = Some((job.end, job.sample_rate, job.channels));
+            out
+        };
+
+        #[cfg(not(feature = "real_ffmpeg"))]
+        let samples = {
+            // Mock: a 440 Hz tone, phase-locked to source time so
+            // adjacent ranges join without clicks.
+            let first = ticks_to_samples(job.start, job.sample_rate);
+            let mut out = Vec::with_capacity(wanted);
+            for i in 0..job.frames() {
+                let t = (first + i) as f64 / f64::from(job.sample_rate);
+                let v = (t * 440.0 * std::f64::consts::TAU).sin() as f32 * 0.25;
+                out.extend(std::iter::repeat_n(v, channels));
+            }
+            out
+        };
+
+        let mut samples = samples;
+        samples.resize(wanted, 0.0);
+        Ok(AudioBuffer {
+            sample_rate: job.sample_rate,
+            channels: job.channels,
+            start: job.start,
+            samples,
+        })
+    }
+
+    #[cfg(feature = "real_ffmpeg")]
+    fn resample_into(
+        &mut self,
+        frame: &ffmpeg::util::frame::Audio,
+        job: &AudioJob,
+        out: &mut Vec<f32>,
+        skip: &mut usize,
+    ) -> MediaResult<()> {
+        use ffmpeg::{ChannelLayout, format::Sample, format::sample::Type};
+
+        let stale = !matches!(&self.resampler, Some((rate, ch, _)) if *rate == job.sample_rate && *ch == job.channels);
+        if stale {
+            let ctx = ffmpeg::software::resampling::Context::get(
+                self.decoder.format(),
+                self.decoder.channel_layout(),
+                self.decoder.rate(),
+                Sample::F32(Type::Packed),
+                ChannelLayout::default(i32::from(job.channels)),
+                job.sample_rate,
+            )
+            .map_err(|e| MediaError::Other {
+                path: self.path.clone(),
+                reason: format!("Failed to create resampler: {}", e),
+            })?;
+            self.resampler = Some((job.sample_rate, job.channels, ctx));
+        }
+        let (_, _, resampler) = self.resampler.as_mut().unwrap();
+
+        let mut converted = ffmpeg::util::frame::Audio::empty();
+        resampler
+            .run(frame, &mut converted)
+            .map_err(|e| MediaError::Other {
+                path: self.path.clone(),
+                reason: format!("Resampling failed: {}", e),
+            })?;
+        push_samples(&converted, job.channels, out, skip);
+        Ok(())
+    }
+
+    /// Drains the samples the resampler still holds at the end of the stream.
+    #[cfg(feature = "real_ffmpeg")]
+    fn flush_resampler(
+        &mut self,
+        job: &AudioJob,
+        out: &mut Vec<f32>,
+        skip: &mut usize,
+    ) -> MediaResult<()> {
+        use ffmpeg::{ChannelLayout, format::Sample, format::sample::Type};
+
+        let Some((_, _, resampler)) = self.resampler.as_mut() else {
+            return Ok(());
+        };
+        let pending = resampler.delay().map_or(0, |d| d.output.max(0) as usize);
+        if pending == 0 {
+            return Ok(());
+        }
+        let mut converted = ffmpeg::util::frame::Audio::new(
+            Sample::F32(Type::Packed),
+            pending,
+            ChannelLayout::default(i32::from(job.channels)),
+        );
+        resampler
+            .flush(&mut converted)
+            .map_err(|e| MediaError::Other {
+                path: self.path.clone(),
+                reason: format!("Resampler flush failed: {}", e),
+            })?;
+        push_samples(&converted, job.channels, out, skip);
+        Ok(())
+    }
+}
+
+/// Appends packed `f32` samples to `out`, dropping the first `skip`.
+#[cfg(feature = "real_ffmpeg")]
+fn push_samples(
+    converted: &ffmpeg::util::frame::Audio,
+    channels: u16,
+    out: &mut Vec<f32>,
+    skip: &mut usize,
+) {
+    // Packed output lives entirely in plane 0.
+    let len = converted.samples() * usize::from(channels) * 4;
+    let bytes = &converted.data(0)[..len];
+    for chunk in bytes.chunks_exact(4) {
+        if *skip > 0 {
+            *skip -= 1;
+            continue;
+        }
+        out.push(f32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]));
+    }
+}
+
+/// Audio decoders by path, one per source since the audio of a clip is
+/// requested in order. Like the video [`DecoderPool`](crate::pool::DecoderPool)
+/// it keeps at most `max_open_decoders` open; opening one more closes the
+/// least recently used decoder no job is holding.
+#[derive(Default)]
+pub struct AudioDecoders {
+    state: Mutex<AudioState>,
+}
+
+/// A decoder shared with the jobs for its source, opened by the first one.
+pub type AudioSlot = Arc<Mutex<Option<AudioDecoder>>>;
+
+#[derive(Default)]
+struct AudioState {
+    slots: HashMap<String, (AudioSlot, u64)>,
+    limit: usize,
+    clock: u64,
+}
+
+impl AudioDecoders {
+    pub fn new(limit: usize) -> Self {
+        let pool = Self::default();
+        pool.set_limit(limit);
+        pool
+    }
+
+    pub fn set_limit(&self, limit: usize) {
+        let mut st = self.state.lock().unwrap();
+        st.limit = limit.max(1);
+        st.close_lru_idle(None);
+    }
+
+    /// The decoder for `path`, with an empty slot if none is open yet.
+    pub fn slot(&self, path: &str) -> AudioSlot {
+        let mut st = self.state.lock().unwrap();
+        st.clock += 1;
+        let clock = st.clock;
+        let (slot, last_used) = st.slots.entry(path.to_string()).or_default();
+        *last_used = clock;
+        let slot = slot.clone();
+        st.close_lru_idle(Some(path));
+        slot
+    }
+
+    pub fn clear(&self) {
+        self.state.lock().unwrap().slots.clear();
+    }
+}
+
+impl AudioState {
+    /// Closes least recently used decoders other than `keep` until at most
+    /// `limit` are open. Decoders a job still holds stay open.
+    fn close_lru_idle(&mut self, keep: Option<&str>) {
+        while self.slots.len() > self.limit {
+            let victim = self
+                .slots
+                .iter()
+                .filter(|(path, (slot, _))| {
+                    keep != Some(path.as_str()) && Arc::strong_count(slot) == 1
+                })
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(path, _)| path.clone());
+            let Some(path) = victim else {
+                break;
+            };
+            self.slots.remove(&path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(start: u64, end: u64, sample_rate: u32) -> AudioJob {
+        AudioJob {
+            path: "clip.wav".to_string(),
+            start,
+            end,
+            sample_rate,
+            channels: 2,
+        }
+    }
+
+    #[test]
+    fn adjacent_jobs_split_samples_exactly() {
+        // A 24000/1001 frame, which is not a whole number of samples.
+        let frame = tps() * 1001 / 24000;
+        for sample_rate in [44_100, 48_000] {
+            let whole = job(0, 100 * frame, sample_rate).frames();
+            let split: usize = (0..100)
+                .map(|n| job(n * frame, (n + 1) * frame, sample_rate).frames())
+                .sum();
+            assert_eq!(split, whole, "{sample_rate} Hz");
+        }
+        assert_eq!(job(0, tps(), 48_000).frames(), 48_000);
+    }
+
+    #[test]
+    fn least_recently_used_idle_decoder_is_closed() {
+        let pool = AudioDecoders::new(2);
+        let a = pool.slot("a.wav");
+        drop(pool.slot("b.wav"));
+        pool.slot("a.wav");
+        // b is the least recently used; a is still held by a job anyway.
+        let _c = pool.slot("c.wav");
+        let open = |path: &str| pool.state.lock().unwrap().slots.contains_key(path);
+        assert!(open("a.wav") && open("c.wav"));
+        assert!(!open("b.wav"));
+
+        // With every decoder held, the limit is exceeded until one is free.
+        let _d = pool.slot("d.wav");
+        assert!(open("a.wav") && open("c.wav") && open("d.wav"));
+        drop(a);
+        pool.slot("d.wav");
+        assert!(!open("a.wav"));
+        assert_eq!(pool.state.lock().unwrap().slots.len(), 2);
+    }
+}
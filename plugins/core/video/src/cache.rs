@@ -0,0 +1,367 @@
+//! Byte-budgeted cache of decoded frames, consulted before any decode is scheduled.
+//!
+//! Entries are keyed by source, source frame, output spec and view, so the same frame
+//! at preview and full resolution are cached separately. The most recently used
+//! entries stay uncompressed; with compression enabled, older ("cold") entries
+//! are packed with lz4 or zstd until they are used again. The least recently
+//! used entries are dropped once the budget is exceeded.
+
+use crate::{
+    color::ColorInfo,
+    components::{CacheCompression, FrameCacheSettings},
+    frame::{FrameSpec, VideoFrame},
+    geometry::FrameView,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FrameKey {
+    pub source: String,
+    /// Index of the frame in the source at its own rate.
+    pub frame: u64,
+    pub spec: FrameSpec,
+    pub view: FrameView,
+}
+
+enum Stored {
+    Raw(VideoFrame),
+    Packed {
+        codec: CacheCompression,
+        /// Compressed planes with their decompressed lengths.
+        planes: Vec<(Vec<u8>, usize)>,
+        color: ColorInfo,
+    },
+}
+
+struct Entry {
+    stored: Stored,
+    last_used: u64,
+}
+
+impl Entry {
+    fn byte_len(&self) -> usize {
+        match &self.stored {
+            Stored::Raw(frame) => frame.byte_len(),
+            Stored::Packed { planes, .. } => planes.iter().map(|(p, _)| p.len()).sum(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<FrameKey, Entry>,
+    /// Keys by last use, oldest first.
+    order: BTreeMap<u64, FrameKey>,
+    settings: FrameCacheSettings,
+    /// Bytes held by all entries, as stored.
+    bytes: usize,
+    /// Bytes held by uncompressed entries.
+    raw_bytes: usize,
+    clock: u64,
+}
+
+pub struct FrameCache {
+    state: Mutex<CacheState>,
+}
+
+impl FrameCache {
+    pub fn new(settings: FrameCacheSettings) -> Self {
+        Self {
+            state: Mutex::new(CacheState {
+                settings,
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub fn set_settings(&self, settings: FrameCacheSettings) {
+        let mut st = self.state.lock().unwrap();
+        st.settings = settings;
+        st.rebalance();
+    }
+
+    /// Returns a copy of the cached frame and marks it as recently used.
+    /// Compressed entries are unpacked and kept uncompressed from then on.
+    pub fn get(&self, key: &FrameKey) -> Option<VideoFrame> {
+        let mut guard = self.state.lock().unwrap();
+        let st = &mut *guard;
+        let entry = st.entries.get_mut(key)?;
+        st.order.remove(&entry.last_used);
+        st.clock += 1;
+        entry.last_used = st.clock;
+        st.order.insert(st.clock, key.clone());
+
+        let frame = match &entry.stored {
+            Stored::Raw(frame) => frame.clone(),
+            Stored::Packed { .. } => {
+                let packed_len = entry.byte_len();
+                let Some(frame) = unpack(&entry.stored, key.spec) else {
+                    // Corrupt entry; forget it and decode again.
+                    let entry = st.entries.remove(key).unwrap();
+                    st.order.remove(&entry.last_used);
+                    st.bytes -= packed_len;
+                    return None;
+                };
+                entry.stored = Stored::Raw(frame.clone());
+                st.bytes = st.bytes - packed_len + frame.byte_len();
+                st.raw_bytes += frame.byte_len();
+                st.rebalance();
+                frame
+            }
+        };
+        Some(frame)
+    }
+
+    /// Whether `key` is cached, without counting as a use.
+    pub fn contains(&self, key: &FrameKey) -> bool {
+        self.state.lock().unwrap().entries.contains_key(key)
+    }
+
+    pub fn insert(&self, key: FrameKey, frame: VideoFrame) {
+        let mut st = self.state.lock().unwrap();
+        let len = frame.byte_len();
+        if len > st.settings.max_bytes {
+            return;
+        }
+        st.remove(&key);
+        st.clock += 1;
+        let clock = st.clock;
+        st.order.insert(clock, key.clone());
+        st.entries.insert(
+            key,
+            Entry {
+                stored: Stored::Raw(frame),
+                last_used: clock,
+            },
+        );
+        st.bytes += len;
+        st.raw_bytes += len;
+        st.rebalance();
+    }
+
+    /// Drops every frame of `source`, e.g. once its frames are numbered differently.
+    pub fn remove_source(&self, source: &str) {
+        let mut st = self.state.lock().unwrap();
+        let keys: Vec<FrameKey> = st
+            .entries
+            .keys()
+            .filter(|k| k.source == source)
+            .cloned()
+            .collect();
+        for key in keys {
+            st.remove(&key);
+        }
+    }
+
+    pub fn clear(&self) {
+        let mut st = self.state.lock().unwrap();
+        st.entries.clear();
+        st.order.clear();
+        st.bytes = 0;
+        st.raw_bytes = 0;
+    }
+}
+
+impl CacheState {
+    fn remove(&mut self, key: &FrameKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.order.remove(&entry.last_used);
+            self.forget(&entry);
+        }
+    }
+
+    /// Takes a removed entry out of the byte counts.
+    fn forget(&mut self, entry: &Entry) {
+        self.bytes -= entry.byte_len();
+        if matches!(entry.stored, Stored::Raw(_)) {
+            self.raw_bytes -= entry.byte_len();
+        }
+    }
+
+    /// Compresses the oldest uncompressed entries past the hot budget, then
+    /// evicts from the cold end until everything fits. Both walk `order` in
+    /// place, so keys are never copied.
+    fn rebalance(&mut self) {
+        let Self {
+            entries,
+            order,
+            settings,
+            bytes,
+            raw_bytes,
+            ..
+        } = self;
+        if settings.compression != CacheCompression::None
+            && *raw_bytes > settings.uncompressed_bytes
+        {
+            for key in order.values() {
+                if *raw_bytes <= settings.uncompressed_bytes {
+                    break;
+                }
+                let entry = entries.get_mut(key).unwrap();
+                let Stored::Raw(frame) = &entry.stored else {
+                    continue;
+                };
+                let raw_len = frame.byte_len();
+                let Some(packed) = pack(frame, settings.compression) else {
+                    continue;
+                };
+                entry.stored = packed;
+                *bytes = *bytes - raw_len + entry.byte_len();
+                *raw_bytes -= raw_len;
+            }
+        }
+        while self.bytes > self.settings.max_bytes {
+            let Some((_, key)) = self.order.pop_first() else {
+                break;
+            };
+            let entry = self.entries.remove(&key).unwrap();
+            self.forget(&entry);
+        }
+    }
+}
+
+fn pack(frame: &VideoFrame, codec: CacheCompression) -> Option<Stored> {
+    let planes = frame
+        .planes
+        .iter()
+        .map(|plane| {
+            let packed = match codec {
+                CacheCompression::None => return None,
+                CacheCompression::Lz4 => lz4_flex::compress_prepend_size(plane),
+                CacheCompression::Zstd => zstd::encode_all(plane.as_slice(), 1).ok()?,
+            };
+            Some((packed, plane.len()))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(Stored::Packed {
+        codec,
+        planes,
+        color: frame.color,
+    })
+}
+
+fn unpack(stored: &Stored, spec: FrameSpec) -> Option<VideoFrame> {
+    let Stored::Packed {
+        codec,
+        planes,
+        color,
+    } = stored
+    else {
+        return None;
+    };
+    let planes = planes
+        .iter()
+        .map(|(packed, len)| {
+            let plane = match codec {
+                CacheCompression::None => return None,
+                CacheCompression::Lz4 => lz4_flex::decompress_size_prepended(packed).ok()?,
+                CacheCompression::Zstd => zstd::decode_all(packed.as_slice()).ok()?,
+            };
+            (plane.len() == *len).then_some(plane)
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(VideoFrame {
+        format: spec.format,
+        width: spec.width,
+        height: spec.height,
+        planes,
+        color: *color,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::FrameFormat;
+
+    const SPEC: FrameSpec = FrameSpec {
+        format: FrameFormat::Rgba8,
+        width: 16,
+        height: 16,
+    };
+    const FRAME_BYTES: usize = 16 * 16 * 4;
+
+    fn key(frame: u64) -> FrameKey {
+        FrameKey {
+            source: "clip.mov".to_string(),
+            frame,
+            spec: SPEC,
+            view: FrameView::default(),
+        }
+    }
+
+    fn frame(seed: u8) -> VideoFrame {
+        VideoFrame {
+            format: SPEC.format,
+            width: SPEC.width,
+            height: SPEC.height,
+            planes: vec![(0..FRAME_BYTES).map(|i| (i % 16) as u8 ^ seed).collect()],
+            color: ColorInfo::default(),
+        }
+    }
+
+    fn cache(max_frames: usize, hot_frames: usize, compression: CacheCompression) -> FrameCache {
+        FrameCache::new(FrameCacheSettings {
+            max_bytes: max_frames * FRAME_BYTES,
+            uncompressed_bytes: hot_frames * FRAME_BYTES,
+            compression,
+        })
+    }
+
+    #[test]
+    fn least_recently_used_frames_are_evicted() {
+        let cache = cache(3, 3, CacheCompression::None);
+        for n in 0..3 {
+            cache.insert(key(n), frame(n as u8));
+        }
+        // Frame 0 is used again, so frame 1 is now the oldest.
+        assert!(cache.get(&key(0)).is_some());
+        cache.insert(key(3), frame(3));
+
+        assert!(cache.contains(&key(0)));
+        assert!(!cache.contains(&key(1)));
+        assert!(cache.contains(&key(2)));
+        assert!(cache.contains(&key(3)));
+        assert_eq!(cache.state.lock().unwrap().bytes, 3 * FRAME_BYTES);
+    }
+
+    #[test]
+    fn frames_over_the_budget_are_not_cached() {
+        let cache = cache(1, 1, CacheCompression::None);
+        cache.insert(key(0), frame(0));
+        let mut big = frame(1);
+        big.planes.push(vec![0; 1]);
+        cache.insert(key(1), big);
+
+        assert!(cache.contains(&key(0)));
+        assert!(!cache.contains(&key(1)));
+    }
+
+    #[test]
+    fn cold_frames_unpack_to_the_same_bytes() {
+        for compression in [CacheCompression::Lz4, CacheCompression::Zstd] {
+            let cache = cache(8, 1, compression);
+            let original = frame(7);
+            cache.insert(key(0), original.clone());
+            cache.insert(key(1), frame(8));
+            {
+                let st = cache.state.lock().unwrap();
+                assert!(matches!(st.entries[&key(0)].stored, Stored::Packed { .. }));
+                assert!(st.bytes < 2 * FRAME_BYTES, "{compression:?} did not shrink");
+                assert_eq!(st.raw_bytes, FRAME_BYTES);
+            }
+
+            let unpacked = cache.get(&key(0)).unwrap();
+            assert_eq!(unpacked.planes, original.planes, "{compression:?}");
+            assert_eq!(unpacked.color, original.color);
+            assert_eq!((unpacked.width, unpacked.height), (SPEC.width, SPEC.height));
+            // Using it made frame 0 hot again and frame 1 cold.
+            let st = cache.state.lock().unwrap();
+            assert!(matches!(st.entries[&key(0)].stored, Stored::Raw(_)));
+            assert!(matches!(st.entries[&key(1)].stored, Stored::Packed { .. }));
+        }
+    }
+}
@@ -0,0 +1,405 @@
+//! Colour description of decoded frames and conversion into the project's
+//! working space.
+//!
+//! Every [`VideoFrame`](crate::frame::VideoFrame) carries the [`ColorInfo`] of
+//! its source: primaries, transfer function, YUV matrix and range, and HDR
+//! mastering metadata when the stream has it. Nothing relies on swscale's
+//! default matrices; YUV samples are decoded with the matrix and range the
+//! stream is tagged with, linearised, moved to the working primaries and
+//! re-encoded with the working transfer.
+//!
+//! Linear light is relative to SDR reference white, which BT.2408 puts at
+//! 203 cd/m² for PQ and HLG. HDR sources converted into an SDR working space
+//! are tone mapped towards the peak from their metadata.
+
+/// Chromaticities of the red, green and blue primaries (all with a D65 white).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Primaries {
+    #[default]
+    Bt709,
+    /// SMPTE 170M, 525-line SD.
+    Bt601,
+    /// BT.470 B/G, 625-line SD.
+    Bt470Bg,
+    Bt2020,
+    /// P3 with a D65 white point.
+    DisplayP3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Transfer {
+    /// BT.709 signals shown on a BT.1886 (2.4 gamma) display.
+    #[default]
+    Bt709,
+    Srgb,
+    Linear,
+    /// SMPTE ST 2084 perceptual quantizer.
+    Pq,
+    /// ARIB STD-B67 hybrid log-gamma.
+    Hlg,
+}
+
+/// Weights used to form Y'CbCr from R'G'B'.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Matrix {
+    Bt601,
+    #[default]
+    Bt709,
+    /// BT.2020 non-constant luminance.
+    Bt2020,
+    /// Samples are R'G'B' already.
+    Rgb,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Range {
+    /// Studio swing: 16-235 luma and 16-240 chroma at 8 bits.
+    #[default]
+    Limited,
+    Full,
+}
+
+/// Static HDR metadata, in cd/m².
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HdrMetadata {
+    /// Maximum content light level; 0 if unknown.
+    pub max_cll: f32,
+    /// Maximum frame-average light level; 0 if unknown.
+    pub max_fall: f32,
+    /// Luminance range of the mastering display; 0 if unknown.
+    pub mastering_min: f32,
+    pub mastering_max: f32,
+}
+
+impl HdrMetadata {
+    /// Brightest level the content is expected to reach.
+    pub fn peak(&self) -> Option<f32> {
+        [self.max_cll, self.mastering_max]
+            .into_iter()
+            .find(|&nits| nits > 0.0)
+    }
+}
+
+/// How the samples of a frame are to be interpreted. `matrix` and `range`
+/// only matter for YUV layouts; RGB layouts are always full range.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ColorInfo {
+    pub primaries: Primaries,
+    pub transfer: Transfer,
+    pub matrix: Matrix,
+    pub range: Range,
+    pub hdr: Option<HdrMetadata>,
+}
+
+impl ColorInfo {
+    /// PNG, JPEG and QOI images.
+    pub const SRGB: Self = Self {
+        primaries: Primaries::Bt709,
+        transfer: Transfer::Srgb,
+        matrix: Matrix::Rgb,
+        range: Range::Full,
+        hdr: None,
+    };
+
+    /// Scene-linear images such as OpenEXR.
+    pub const LINEAR: Self = Self {
+        primaries: Primaries::Bt709,
+        transfer: Transfer::Linear,
+        matrix: Matrix::Rgb,
+        range: Range::Full,
+        hdr: None,
+    };
+
+    pub fn is_hdr(&self) -> bool {
+        matches!(self.transfer, Transfer::Pq | Transfer::Hlg)
+    }
+}
+
+/// The space frames are converted into before they leave the plugin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct WorkingSpace {
+    pub primaries: Primaries,
+    pub transfer: Transfer,
+}
+
+impl WorkingSpace {
+    /// Description of frames in this space. YUV layouts use the matrix that
+    /// belongs to the primaries, limited range.
+    pub fn color_info(&self) -> ColorInfo {
+        ColorInfo {
+            primaries: self.primaries,
+            transfer: self.transfer,
+            matrix: match self.primaries {
+                Primaries::Bt2020 => Matrix::Bt2020,
+                Primaries::Bt601 | Primaries::Bt470Bg => Matrix::Bt601,
+                Primaries::Bt709 | Primaries::DisplayP3 => Matrix::Bt709,
+            },
+            range: Range::Limited,
+            hdr: None,
+        }
+    }
+}
+
+impl Matrix {
+    /// `(Kr, Kb)`, or `None` for RGB.
+    pub fn coefficients(self) -> Option<(f32, f32)> {
+        match self {
+            Self::Bt601 => Some((0.299, 0.114)),
+            Self::Bt709 => Some((0.2126, 0.0722)),
+            Self::Bt2020 => Some((0.2627, 0.0593)),
+            Self::Rgb => None,
+        }
+    }
+}
+
+/// SDR reference white in cd/m² (BT.2408).
+const REFERENCE_WHITE: f32 = 203.0;
+/// Peak assumed for HDR content without metadata, and for HLG displays.
+const DEFAULT_PEAK: f32 = 1000.0;
+
+impl Transfer {
+    /// Signal value to linear light, 1.0 being reference white.
+    pub fn to_linear(self, v: f32) -> f32 {
+        let v = v.max(0.0);
+        match self {
+            Self::Bt709 => v.powf(2.4),
+            Self::Srgb if v <= 0.04045 => v / 12.92,
+            Self::Srgb => ((v + 0.055) / 1.055).powf(2.4),
+            Self::Linear => v,
+            Self::Pq => pq_eotf(v) * 10_000.0 / REFERENCE_WHITE,
+            // Per-channel approximation of the BT.2100 OOTF for a 1000 cd/m² display.
+            Self::Hlg => hlg_inverse_oetf(v).powf(1.2) * DEFAULT_PEAK / REFERENCE_WHITE,
+        }
+    }
+
+    /// Linear light to signal value; the inverse of [`Transfer::to_linear`].
+    pub fn from_linear(self, l: f32) -> f32 {
+        let l = l.max(0.0);
+        match self {
+            Self::Bt709 => l.powf(1.0 / 2.4),
+            Self::Srgb if l <= 0.003_130_8 => l * 12.92,
+            Self::Srgb => 1.055 * l.powf(1.0 / 2.4) - 0.055,
+            Self::Linear => l,
+            Self::Pq => pq_inverse_eotf(l * REFERENCE_WHITE / 10_000.0),
+            Self::Hlg => hlg_oetf((l * REFERENCE_WHITE / DEFAULT_PEAK).powf(1.0 / 1.2)),
+        }
+    }
+}
+
+const PQ_M1: f32 = 2610.0 / 16384.0;
+const PQ_M2: f32 = 2523.0 / 4096.0 * 128.0;
+const PQ_C1: f32 = 3424.0 / 4096.0;
+const PQ_C2: f32 = 2413.0 / 4096.0 * 32.0;
+const PQ_C3: f32 = 2392.0 / 4096.0 * 32.0;
+
+/// PQ signal to display light as a fraction of 10000 cd/m².
+fn pq_eotf(e: f32) -> f32 {
+    let p = e.min(1.0).powf(1.0 / PQ_M2);
+    ((p - PQ_C1).max(0.0) / (PQ_C2 - PQ_C3 * p)).powf(1.0 / PQ_M1)
+}
+
+fn pq_inverse_eotf(y: f32) -> f32 {
+    let p = y.clamp(0.0, 1.0).powf(PQ_M1);
+    ((PQ_C1 + PQ_C2 * p) / (1.0 + PQ_C3 * p)).powf(PQ_M2)
+}
+
+const HLG_A: f32 = 0.178_832_77;
+const HLG_B: f32 = 0.284_668_92;
+const HLG_C: f32 = 0.559_910_7;
+
+fn hlg_inverse_oetf(e: f32) -> f32 {
+    if e <= 0.5 {
+        e * e / 3.0
+    } else {
+        ((e.min(1.0) - HLG_C) / HLG_A).exp() / 12.0 + HLG_B / 12.0
+    }
+}
+
+fn hlg_oetf(l: f32) -> f32 {
+    let l = l.clamp(0.0, 1.0);
+    if l <= 1.0 / 12.0 {
+        (3.0 * l).sqrt()
+    } else {
+        HLG_A * (12.0 * l - HLG_B).ln() + HLG_C
+    }
+}
+
+type Mat3 = [[f64; 3]; 3];
+
+impl Primaries {
+    /// `(x, y)` of red, green, blue and white.
+    fn chromaticities(self) -> [(f64, f64); 4] {
+        const D65: (f64, f64) = (0.3127, 0.3290);
+        match self {
+            Self::Bt709 => [(0.640, 0.330), (0.300, 0.600), (0.150, 0.060), D65],
+            Self::Bt601 => [(0.630, 0.340), (0.310, 0.595), (0.155, 0.070), D65],
+            Self::Bt470Bg => [(0.640, 0.330), (0.290, 0.600), (0.150, 0.060), D65],
+            Self::Bt2020 => [(0.708, 0.292), (0.170, 0.797), (0.131, 0.046), D65],
+            Self::DisplayP3 => [(0.680, 0.320), (0.265, 0.690), (0.150, 0.060), D65],
+        }
+    }
+
+    /// Linear RGB in these primaries to CIE XYZ.
+    fn to_xyz(self) -> Mat3 {
+        let [r, g, b, w] = self.chromaticities();
+        let xyz = |(x, y): (f64, f64)| [x / y, 1.0, (1.0 - x - y) / y];
+        let (r, g, b, w) = (xyz(r), xyz(g), xyz(b), xyz(w));
+        let m = [[r[0], g[0], b[0]], [r[1], g[1], b[1]], [r[2], g[2], b[2]]];
+        // Scale each primary so that RGB (1, 1, 1) lands on the white point.
+        let s = mul_vec(&invert(&m), w);
+        std::array::from_fn(|i| std::array::from_fn(|j| m[i][j] * s[j]))
+    }
+}
+
+fn mul(a: &Mat3, b: &Mat3) -> Mat3 {
+    std::array::from_fn(|i| std::array::from_fn(|j| (0..3).map(|k| a[i][k] * b[k][j]).sum()))
+}
+
+fn mul_vec(m: &Mat3, v: [f64; 3]) -> [f64; 3] {
+    std::array::from_fn(|i| (0..3).map(|k| m[i][k] * v[k]).sum())
+}
+
+fn invert(m: &Mat3) -> Mat3 {
+    let cof =
+        |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+    let adj = [
+        [cof(1, 2, 1, 2), -cof(0, 2, 1, 2), cof(0, 1, 1, 2)],
+        [-cof(1, 2, 0, 2), cof(0, 2, 0, 2), -cof(0, 1, 0, 2)],
+        [cof(1, 2, 0, 1), -cof(0, 2, 0, 1), cof(0, 1, 0, 1)],
+    ];
+    let det = m[0][0] * adj[0][0] + m[0][1] * adj[1][0] + m[0][2] * adj[2][0];
+    adj.map(|row| row.map(|v| v / det))
+}
+
+/// Maps non-linear RGB from one colour space to another.
+pub struct Conversion {
+    from: Transfer,
+    to: Transfer,
+    /// Linear RGB to linear RGB, `None` when the primaries agree.
+    gamut: Option<[[f32; 3]; 3]>,
+    /// Peak of the source relative to reference white, when HDR is squeezed into SDR.
+    tone_map_peak: Option<f32>,
+}
+
+impl Conversion {
+    /// `None` when samples in `from` already mean the same in `to`.
+    pub fn new(from: &ColorInfo, to: &ColorInfo) -> Option<Self> {
+        if from.primaries == to.primaries && from.transfer == to.transfer {
+            return None;
+        }
+        let gamut = (from.primaries != to.primaries).then(|| {
+            let m = mul(&invert(&to.primaries.to_xyz()), &from.primaries.to_xyz());
+            m.map(|row| row.map(|v| v as f32))
+        });
+        let tone_map_peak = (from.is_hdr() && !to.is_hdr() && to.transfer != Transfer::Linear)
+            .then(|| from.hdr.and_then(|h| h.peak()).unwrap_or(DEFAULT_PEAK) / REFERENCE_WHITE);
+        Some(Self {
+            from: from.transfer,
+            to: to.transfer,
+            gamut,
+            tone_map_peak,
+        })
+    }
+
+    pub fn apply(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let mut lin = rgb.map(|v| self.from.to_linear(v));
+        if let Some(m) = &self.gamut {
+            lin = std::array::from_fn(|i| (0..3).map(|k| m[i][k] * lin[k]).sum());
+        }
+        if let Some(peak) = self.tone_map_peak {
+            // Extended Reinhard on the largest component keeps hues while
+            // rolling highlights off so that `peak` lands on white.
+            let max = lin[0].max(lin[1]).max(lin[2]);
+            if max > 0.0 {
+                let mapped = max * (1.0 + max / (peak * peak)) / (1.0 + max);
+                lin = lin.map(|v| v * mapped / max);
+            }
+        }
+        lin.map(|l| self.to.from_linear(l))
+    }
+}
+
+/// Colour description of a decoded frame, with the usual guesses for
+/// untagged streams: BT.601 up to 576 lines, BT.709 above.
+#[cfg(feature = "real_ffmpeg")]
+pub fn from_frame(frame: &ffmpeg_next::util::frame::Video, is_rgb: bool) -> ColorInfo {
+    use ffmpeg_next::{color, ffi, format::Pixel, util::frame::side_data::Type};
+
+    let matrix = match frame.color_space() {
+        _ if is_rgb => Matrix::Rgb,
+        color::Space::RGB => Matrix::Rgb,
+        color::Space::BT709 => Matrix::Bt709,
+        color::Space::BT470BG | color::Space::SMPTE170M | color::Space::FCC => Matrix::Bt601,
+        color::Space::BT2020NCL | color::Space::BT2020CL => Matrix::Bt2020,
+        _ if frame.height() <= 576 => Matrix::Bt601,
+        _ => Matrix::Bt709,
+    };
+    let primaries = match frame.color_primaries() {
+        color::Primaries::BT709 => Primaries::Bt709,
+        color::Primaries::SMPTE170M | color::Primaries::SMPTE240M => Primaries::Bt601,
+        color::Primaries::BT470BG => Primaries::Bt470Bg,
+        color::Primaries::BT2020 => Primaries::Bt2020,
+        color::Primaries::SMPTE432 => Primaries::DisplayP3,
+        _ => match matrix {
+            Matrix::Bt2020 => Primaries::Bt2020,
+            Matrix::Bt601 if frame.height() > 480 => Primaries::Bt470Bg,
+            Matrix::Bt601 => Primaries::Bt601,
+            Matrix::Bt709 | Matrix::Rgb => Primaries::Bt709,
+        },
+    };
+    let transfer = match frame.color_transfer_characteristic() {
+        color::TransferCharacteristic::SMPTE2084 => Transfer::Pq,
+        color::TransferCharacteristic::ARIB_STD_B67 => Transfer::Hlg,
+        color::TransferCharacteristic::Linear => Transfer::Linear,
+        color::TransferCharacteristic::IEC61966_2_1 => Transfer::Srgb,
+        _ => Transfer::Bt709,
+    };
+    // The YUVJ formats are full range whatever the tag says.
+    let range = match (frame.color_range(), frame.format()) {
+        (_, Pixel::YUVJ420P | Pixel::YUVJ422P | Pixel::YUVJ444P | Pixel::YUVJ440P) => Range::Full,
+        (color::Range::JPEG, _) => Range::Full,
+        _ if is_rgb => Range::Full,
+        _ => Range::Limited,
+    };
+
+    // SAFETY: FFmpeg sizes these side data buffers for the structs they hold.
+    let mastering = frame
+        .side_data(Type::MasteringDisplayMetadata)
+        .map(|sd| unsafe {
+            let m = &*(sd.data().as_ptr() as *const ffi::AVMasteringDisplayMetadata);
+            let q = |r: ffi::AVRational| {
+                if r.den == 0 {
+                    0.0
+                } else {
+                    r.num as f32 / r.den as f32
+                }
+            };
+            if m.has_luminance != 0 {
+                (q(m.min_luminance), q(m.max_luminance))
+            } else {
+                (0.0, 0.0)
+            }
+        });
+    let light = frame.side_data(Type::ContentLightLevel).map(|sd| unsafe {
+        let c = &*(sd.data().as_ptr() as *const ffi::AVContentLightMetadata);
+        (c.MaxCLL as f32, c.MaxFALL as f32)
+    });
+    let hdr = (mastering.is_some() || light.is_some()).then(|| {
+        let (mastering_min, mastering_max) = mastering.unwrap_or_default();
+        let (max_cll, max_fall) = light.unwrap_or_default();
+        HdrMetadata {
+            max_cll,
+            max_fall,
+            mastering_min,
+            mastering_max,
+        }
+    });
+
+    ColorInfo {
+        primaries,
+        transfer,
+        matrix,
+        range,
+        hdr,
+    }
+}
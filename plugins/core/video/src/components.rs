@@ -1,6 +1,217 @@
+use crate::{
+    color::WorkingSpace,
+    frame::{Downscale, FrameFormat},
+    geometry::{FieldOrder, Geometry},
+    time::Rational,
+};
 use lunaris_ecs::prelude::*;
 
-#[derive(Component, Debug, Clone)]
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
 pub struct VideoSource {
     pub path: String,
 }
+
+/// Stream metadata of a `VideoSource`, filled in by the plugin when the source is added.
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct MediaInfo {
+    /// Container duration in ticks; 0 if unknown.
+    pub duration: u64,
+    pub frame_rate: Option<Rational>,
+    pub time_base: Option<Rational>,
+    pub width: u32,
+    pub height: u32,
+    pub pixel_format: Option<String>,
+    pub color_space: Option<String>,
+    /// Clockwise display rotation in degrees (0, 90, 180 or 270 in practice).
+    pub rotation: i32,
+    pub field_order: FieldOrder,
+    /// Sample aspect ratio; 1:1 for square pixels.
+    pub pixel_aspect: Rational,
+    pub video_codec: Option<String>,
+    pub audio_codec: Option<String>,
+    pub sample_rate: u32,
+    pub audio_channels: u16,
+    pub channel_layout: Option<String>,
+    /// Size of the file in bytes; 0 if unknown. Used to recognise it when relinking.
+    pub file_size: u64,
+}
+
+impl MediaInfo {
+    pub fn geometry(&self) -> Geometry {
+        Geometry {
+            field_order: self.field_order,
+            rotation: self.rotation,
+            pixel_aspect: self.pixel_aspect,
+        }
+    }
+
+    /// Size as shown to the viewer, after pixel-aspect correction and rotation.
+    pub fn display_size(&self) -> (u32, u32) {
+        self.geometry().display_size(self.width, self.height)
+    }
+}
+
+/// Replaces the field order the stream reports for the `VideoSource` on this
+/// entity. `FieldOrder::Progressive` turns deinterlacing off.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldOrderOverride(pub FieldOrder);
+
+/// Replaces the display rotation (clockwise degrees, snapped to quarter turns).
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RotationOverride(pub i32);
+
+/// Replaces the sample aspect ratio, e.g. 64:45 for widescreen PAL DV.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelAspectOverride(pub Rational);
+
+/// Set by the plugin on sources whose frames are not evenly spaced, once their
+/// frame index is built. Such sources map time through the index unless the
+/// clip conforms them with a [`ConformRate`].
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VariableFrameRate {
+    pub frames: u64,
+    /// Mean rate over the whole stream.
+    pub average: Option<Rational>,
+}
+
+/// On a clip: play the source's frames back to back at this rate, ignoring
+/// their timestamps. Render jobs pass the same as `conform_rate`.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConformRate(pub Rational);
+
+/// Low-resolution stand-in for the `VideoSource` on the same entity, used when
+/// a render job asks for `quality = "preview"`.
+///
+/// Insert with [`ProxyStatus::Requested`] to have the proxy generated in the background.
+#[derive(Component, Debug, Clone)]
+pub struct ProxyMedia {
+    pub path: String,
+    pub status: ProxyStatus,
+}
+
+impl ProxyMedia {
+    /// Requests a proxy at the default location next to the source.
+    pub fn request(source: &VideoSource) -> Self {
+        Self {
+            path: crate::proxy::default_proxy_path(std::path::Path::new(&source.path))
+                .to_string_lossy()
+                .to_string(),
+            status: ProxyStatus::Requested,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProxyStatus {
+    Requested,
+    Generating,
+    Ready,
+    Failed(String),
+}
+
+/// Limits for the per-source decoder pool.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecoderSettings {
+    /// Open decoder handles across all sources. Idle handles of the least
+    /// recently used source are closed first once this is reached.
+    pub max_open_decoders: usize,
+    /// Handles that may decode the same source concurrently.
+    pub max_decoders_per_source: usize,
+}
+
+impl Default for DecoderSettings {
+    fn default() -> Self {
+        let cpus = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(4);
+        Self {
+            max_open_decoders: 32,
+            max_decoders_per_source: cpus.clamp(1, 4),
+        }
+    }
+}
+
+/// Budget and compression of the decoded frame cache.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameCacheSettings {
+    /// Bytes held by cached frames, compressed entries counted as stored.
+    pub max_bytes: usize,
+    /// Bytes of recently used frames kept uncompressed when `compression` is on.
+    pub uncompressed_bytes: usize,
+    pub compression: CacheCompression,
+}
+
+impl Default for FrameCacheSettings {
+    fn default() -> Self {
+        Self {
+            max_bytes: 2 << 30,
+            uncompressed_bytes: 512 << 20,
+            compression: CacheCompression::None,
+        }
+    }
+}
+
+/// How cold cache entries are packed. Lz4 is cheap to unpack while scrubbing;
+/// zstd fits more frames in the same budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CacheCompression {
+    #[default]
+    None,
+    Lz4,
+    Zstd,
+}
+
+/// Colour space decoded frames are delivered in. Sources are converted from
+/// their own primaries and transfer; HDR sources are tone mapped when the
+/// working space is SDR.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ColorSettings {
+    pub working: WorkingSpace,
+}
+
+/// Read-ahead while the playhead moves. Prefetched frames land in the frame
+/// cache, so `format` and `downscale` should match what the player requests.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct PrefetchSettings {
+    /// Frames decoded ahead of the playhead per clip; 0 turns read-ahead off.
+    pub frames: usize,
+    pub format: FrameFormat,
+    pub downscale: Downscale,
+}
+
+impl Default for PrefetchSettings {
+    fn default() -> Self {
+        Self {
+            frames: 24,
+            format: FrameFormat::Rgba8,
+            downscale: Downscale::default(),
+        }
+    }
+}
+
+/// Directory, normally next to the project file, where per-source frame
+/// indexes are cached. Without it indexes are rebuilt every session.
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct MediaCacheDir(pub std::path::PathBuf);
+
+/// Insert to have every `VideoSource` checked for missing files again; removed
+/// once done. Sources are also checked when first seen and when repointed.
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct CheckMedia;
+
+/// Searches `directory` (recursively) for the files of every offline source and
+/// repoints the ones found. Insert with [`RelinkStatus::Requested`].
+#[derive(Resource, Debug, Clone)]
+pub struct RelinkMedia {
+    pub directory: std::path::PathBuf,
+    pub status: RelinkStatus,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RelinkStatus {
+    Requested,
+    Searching,
+    /// Number of sources repointed, counting every source that shared a path.
+    Done { relinked: usize },
+    Failed(String),
+}
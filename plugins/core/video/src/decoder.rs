@@ -1,171 +1,731 @@
 #[cfg(feature = "real_ffmpeg")]
 use ffmpeg_next as ffmpeg;
-use lunaris_api::{
-    render::{PixelFormat, RawImage},
-    util::error::{LunarisError, Result},
+use crate::{
+    color::ColorInfo,
+    error::{MediaError, MediaResult},
+    frame::{FrameSpec, VideoFrame},
+    geometry::{FieldOrder, FrameView, Geometry},
+    image_source::{self, ImageSource},
+    index::FrameIndex,
+    time::Rational,
 };
-use std::path::Path;
+use std::{path::Path, sync::Arc};
+
+/// An open frame source: a container stream, or a still or image sequence.
+pub enum Decoder {
+    Stream(StreamDecoder),
+    Image(ImageSource),
+}
+
+impl Decoder {
+    pub fn new(path: &Path) -> MediaResult<Self> {
+        if image_source::is_image_path(path) {
+            ImageSource::open(path).map(Self::Image)
+        } else {
+            StreamDecoder::new(path).map(Self::Stream)
+        }
+    }
+
+    /// Nominal frame rate of the source.
+    pub fn frame_rate(&self) -> Rational {
+        match self {
+            Self::Stream(d) => d.frame_rate(),
+            Self::Image(s) => s.frame_rate(),
+        }
+    }
+
+    /// Sets the playback rate of image sequences; streams keep their own timing.
+    pub fn set_image_rate(&mut self, rate: Rational) {
+        if let Self::Image(s) = self {
+            s.set_frame_rate(rate);
+        }
+    }
+
+    /// Coded size of the source.
+    pub fn size(&self) -> (u32, u32) {
+        match self {
+            Self::Stream(d) => d.size(),
+            Self::Image(s) => s.size(),
+        }
+    }
+
+    /// Field order, rotation and pixel aspect as stored in the source.
+    pub fn geometry(&self) -> Geometry {
+        match self {
+            Self::Stream(d) => d.geometry(),
+            Self::Image(_) => Geometry::default(),
+        }
+    }
+
+    /// Hands streams the packet index of their file, for direct keyframe seeks.
+    pub fn set_index(&mut self, index: Arc<FrameIndex>) {
+        if let Self::Stream(d) = self {
+            d.set_index(index);
+        }
+    }
+
+    /// See [`StreamDecoder::cursor`]. Image sources read any frame equally fast.
+    pub fn cursor(&self) -> Option<u64> {
+        match self {
+            Self::Stream(d) => d.cursor(),
+            Self::Image(_) => None,
+        }
+    }
+
+    /// Decodes the frame displayed at `ticks` of source time as described by `spec`,
+    /// deinterlaced, rotated and converted into the working space as `view` says.
+    /// `spec` is the size after rotation.
+    pub fn decode_frame(
+        &mut self,
+        ticks: u64,
+        spec: FrameSpec,
+        view: FrameView,
+    ) -> MediaResult<VideoFrame> {
+        // Quarter turns are applied after decoding, so decode at the swapped size.
+        let stored = if view.rotation.rem_euclid(180) == 90 {
+            FrameSpec {
+                width: spec.height,
+                height: spec.width,
+                ..spec
+            }
+        } else {
+            spec
+        };
+        let frame = match self {
+            Self::Stream(d) => {
+                d.decode_frame(ticks, stored, view.field_order, &view.working.color_info())?
+            }
+            Self::Image(s) => s.decode_frame(ticks, stored)?,
+        };
+        Ok(frame
+            .rotated(view.rotation)
+            .convert(spec.format, &view.working.color_info()))
+    }
+}
 
 #[cfg(feature = "real_ffmpeg")]
-pub struct Decoder {
+pub struct StreamDecoder {
+    path: String,
     input: ffmpeg::format::context::Input,
     decoder: ffmpeg::decoder::Video,
     stream_index: usize,
-    scaler: ffmpeg::software::scaling::Context,
+    // Built on first use for the requested output layout, size and colour.
+    scaler: Option<(ScalerKey, ffmpeg::software::scaling::Context)>,
     width: u32,
     height: u32,
+    time_base: Rational,
+    frame_rate: Rational,
+    geometry: Geometry,
+    start_pts: i64,
+    frame_duration: i64,
+    /// Packet index of the file, once built; see [`crate::index`].
+    index: Option<Arc<FrameIndex>>,
+    /// Frames decoded since the last seek, in presentation order.
+    /// Only the current GOP is kept, capped at `MAX_GOP_FRAMES`.
+    gop: Vec<DecodedFrame>,
+    /// `send_eof` has been issued to the codec.
+    draining: bool,
+    /// The codec has returned every frame up to the end of the stream.
+    eof: bool,
 }
 
+#[cfg(feature = "real_ffmpeg")]
+struct DecodedFrame {
+    pts: i64,
+    frame: ffmpeg::util::frame::Video,
+}
+
+/// Upper bound on frames kept around from the current GOP.
+/// Long-GOP 4K sources would otherwise hold gigabytes of decoded YUV.
+#[cfg(feature = "real_ffmpeg")]
+const MAX_GOP_FRAMES: usize = 64;
+
+/// How far ahead of the last decoded frame (in seconds) we keep decoding
+/// forward instead of seeking.
+#[cfg(feature = "real_ffmpeg")]
+const MAX_FORWARD_SECS: i64 = 2;
+
 #[cfg(not(feature = "real_ffmpeg"))]
-pub struct Decoder {
+pub struct StreamDecoder {
     width: u32,
     height: u32,
+    frame_rate: Rational,
+    source: crate::pattern::SyntheticSource,
 }
 
 // Send is needed because we move Decoder between threads (Orchestrator workers)
-unsafe impl Send for Decoder {}
+unsafe impl Send for StreamDecoder {}
 
-impl Decoder {
-    pub fn new(path: &Path) -> Result<Self> {
+impl StreamDecoder {
+    pub fn new(path: &Path) -> MediaResult<Self> {
         #[cfg(feature = "real_ffmpeg")]
         {
-            let input = ffmpeg::format::input(&path).map_err(|e| LunarisError::Generic {
-                reason: format!("Failed to open video file: {}", e),
-            })?;
+            let path = path.display().to_string();
+            let input =
+                ffmpeg::format::input(&path).map_err(|e| MediaError::from_ffmpeg(&path, e))?;
 
             let stream = input
                 .streams()
                 .best(ffmpeg::media::Type::Video)
-                .ok_or(LunarisError::Generic {
+                .ok_or_else(|| MediaError::Unsupported {
+                    path: path.clone(),
                     reason: "No video stream found".to_string(),
                 })?;
 
             let stream_index = stream.index();
-            let context_decoder =
-                ffmpeg::codec::context::Context::from_parameters(stream.parameters()).map_err(|e| {
-                    LunarisError::Generic {
-                        reason: format!("Failed to create codec context: {}", e),
-                    }
-                })?;
-
-            let decoder = context_decoder
-                .decoder()
-                .video()
-                .map_err(|e| LunarisError::Generic {
-                    reason: format!("Failed to create video decoder: {}", e),
+            let time_base = to_rational(stream.time_base());
+            let start_pts = match stream.start_time() {
+                ffmpeg::ffi::AV_NOPTS_VALUE => 0,
+                t => t,
+            };
+            // avg_frame_rate is what players use; r_frame_rate is the fallback
+            // for containers that leave it unset.
+            let frame_rate = [stream.avg_frame_rate(), stream.rate()]
+                .into_iter()
+                .map(to_rational)
+                .find(Rational::is_valid)
+                .ok_or_else(|| MediaError::Unsupported {
+                    path: path.clone(),
+                    reason: "Video stream has no frame rate".to_string(),
                 })?;
+            // One frame in stream time base, used to decide whether the last
+            // decoded frame still covers a requested timestamp.
+            let geometry = crate::geometry::from_stream(&stream);
+            let frame_duration = (time_base.den * frame_rate.den
+                / (time_base.num * frame_rate.num))
+                .max(1);
+            let decoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters())
+                .and_then(|c| c.decoder().video())
+                .map_err(|e| MediaError::from_ffmpeg(&path, e))?;
 
             let width = decoder.width();
             let height = decoder.height();
 
-            let scaler = ffmpeg::software::scaling::Context::get(
-                decoder.format(),
-                width,
-                height,
-                ffmpeg::format::Pixel::RGBA,
-                width,
-                height,
-                ffmpeg::software::scaling::flag::BILINEAR,
-            )
-            .map_err(|e| LunarisError::Generic {
-                reason: format!("Failed to create scaler: {}", e),
-            })?;
-
             Ok(Self {
+                path,
                 input,
                 decoder,
                 stream_index,
-                scaler,
+                scaler: None,
                 width,
                 height,
+                time_base,
+                frame_rate,
+                geometry,
+                start_pts,
+                frame_duration,
+                index: None,
+                gop: Vec::new(),
+                draining: false,
+                eof: false,
             })
         }
         #[cfg(not(feature = "real_ffmpeg"))]
         {
-            // Mock implementation
+            // Mock implementation: a synthetic pattern chosen by the path
+            let path = path.to_string_lossy();
+            let source = crate::pattern::SyntheticSource::from_path(&path).map_err(|e| {
+                MediaError::Unsupported {
+                    path: path.to_string(),
+                    reason: e.to_string(),
+                }
+            })?;
             Ok(Self {
-                width: 1920,
-                height: 1080,
+                width: source.width,
+                height: source.height,
+                frame_rate: source.frame_rate,
+                source,
             })
         }
     }
 
-    pub fn decode_frame(&mut self, timestamp_ms: i64) -> Result<RawImage> {
+    /// Nominal frame rate of the stream.
+    pub fn frame_rate(&self) -> Rational {
+        self.frame_rate
+    }
+
+    /// Coded size of the source.
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn geometry(&self) -> Geometry {
         #[cfg(feature = "real_ffmpeg")]
         {
-            // Seek to timestamp
-            // Note: This is a simplified seek. Precise seeking is harder.
-            let time_base = self.input.stream(self.stream_index).unwrap().time_base();
-            let seek_ts = (timestamp_ms as f64 / 1000.0 / f64::from(time_base)).round() as i64;
-
-            self.input
-                .seek(seek_ts, ..seek_ts)
-                .map_err(|e| LunarisError::Generic {
-                    reason: format!("Seek failed: {}", e),
-                })?;
+            self.geometry
+        }
+        #[cfg(not(feature = "real_ffmpeg"))]
+        {
+            // Synthetic frames are progressive, upright and square-pixel.
+            Geometry::default()
+        }
+    }
 
-            // Decode loop
-            let mut decoded = ffmpeg::util::frame::Video::empty();
-            for (stream, packet) in self.input.packets() {
-                if stream.index() == self.stream_index {
-                    self.decoder.send_packet(&packet).map_err(|e| LunarisError::Generic {
-                        reason: format!("Packet send failed: {}", e),
-                    })?;
-                    
-                    if self.decoder.receive_frame(&mut decoded).is_ok() {
-                        // We got a frame. Scale it to RGBA.
-                        let mut rgb_frame = ffmpeg::util::frame::Video::empty();
-                        self.scaler.run(&decoded, &mut rgb_frame).map_err(|e| LunarisError::Generic {
-                            reason: format!("Scaling failed: {}", e),
-                        })?;
-
-                        let data = rgb_frame.data(0);
-                        let stride = rgb_frame.stride(0);
-                        
-                        // Copy data tightly packed
-                        let mut bytes = Vec::with_capacity((self.width * self.height * 4) as usize);
-                        for y in 0..self.height {
-                            let start = (y as usize) * stride;
-                            let end = start + (self.width as usize) * 4;
-                            bytes.extend_from_slice(&data[start..end]);
-                        }
-
-                        return RawImage::from_bytes(
-                            PixelFormat::Rgba8Unorm,
-                            self.width,
-                            self.height,
-                            bytes,
-                        );
+    /// Uses `index` for seeking if it was built from this stream.
+    pub fn set_index(&mut self, index: Arc<FrameIndex>) {
+        #[cfg(feature = "real_ffmpeg")]
+        if index.time_base() == self.time_base && index.start_pts() == self.start_pts {
+            self.index = Some(index);
+        }
+        // Synthetic frames are rendered directly; there is nothing to seek.
+        #[cfg(not(feature = "real_ffmpeg"))]
+        let _ = index;
+    }
+
+    /// Source time, in ticks, of the last decoded frame. Requests at or a little
+    /// after this position are served without seeking.
+    pub fn cursor(&self) -> Option<u64> {
+        #[cfg(feature = "real_ffmpeg")]
+        {
+            self.gop
+                .last()
+                .map(|f| crate::time::pts_to_ticks(f.pts - self.start_pts, self.time_base))
+        }
+        #[cfg(not(feature = "real_ffmpeg"))]
+        {
+            None
+        }
+    }
+
+    /// Decodes the frame displayed at `ticks` (source time, see [`lunaris_api::consts::tps`]).
+    ///
+    /// Seeks to the keyframe before the timestamp and decodes forward until the
+    /// frame whose presentation interval contains it. Requests that land in the
+    /// GOP that is already decoded, or shortly after it, are served without seeking.
+    ///
+    /// The frame is returned at the size in `spec`, tagged with its colour
+    /// description. When the stream's primaries and transfer match `target`,
+    /// swscale converts straight to `spec.format` with the stream's matrix and
+    /// range. Otherwise the frame comes back in an intermediate layout that
+    /// swscale has not matrixed, for [`VideoFrame::convert`] to take through
+    /// linear light. YUV layouts are copied as-is when they match the codec's
+    /// output. Interlaced frames are deinterlaced first, see
+    /// [`FrameView::field_order`].
+    pub fn decode_frame(
+        &mut self,
+        ticks: u64,
+        spec: FrameSpec,
+        field_order: Option<FieldOrder>,
+        target: &ColorInfo,
+    ) -> MediaResult<VideoFrame> {
+        #[cfg(feature = "real_ffmpeg")]
+        {
+            let target = self.start_pts + crate::time::ticks_to_pts(ticks, self.time_base);
+
+            if self.lookup(target).is_none() {
+                let forward_window = MAX_FORWARD_SECS * self.time_base.den / self.time_base.num;
+                // With an index, decoding forward pays off until a keyframe
+                // lies in between; without one, for a fixed time window.
+                let key = self.index.as_ref().and_then(|i| i.keyframe_before(target));
+                let can_continue = match (self.gop.last(), key) {
+                    (Some(last), Some(key)) => target > last.pts && key.pts <= last.pts,
+                    (Some(last), None) => target > last.pts && target - last.pts <= forward_window,
+                    (None, _) => false,
+                };
+                if !can_continue {
+                    self.seek(target)?;
+                }
+                while self.lookup(target).is_none() {
+                    if !self.decode_next()? {
+                        break;
                     }
                 }
             }
 
-            Err(LunarisError::Generic {
-                reason: "End of stream or decode error".to_string(),
-            })
+            let idx = match self.lookup(target) {
+                Some(idx) => idx,
+                // The first frame after a seek may start after the target
+                // (e.g. timestamps before the stream start); show it rather than nothing.
+                None if !self.gop.is_empty() && target < self.gop[0].pts => 0,
+                None => {
+                    return Err(MediaError::EndOfStream {
+                        path: self.path.clone(),
+                    });
+                }
+            };
+            convert_frame(
+                &mut self.scaler,
+                &self.gop[idx].frame,
+                spec,
+                field_order,
+                target,
+            )
+            .map_err(|e| MediaError::from_ffmpeg(&self.path, e))
         }
         #[cfg(not(feature = "real_ffmpeg"))]
         {
-            // Return a dummy frame (checkerboard or solid color)
-            // Changing color based on timestamp to simulate playback
-            let r = (timestamp_ms % 255) as u8;
-            let g = ((timestamp_ms / 2) % 255) as u8;
-            let b = ((timestamp_ms / 3) % 255) as u8;
-            
-            let mut data = Vec::with_capacity((self.width * self.height * 4) as usize);
-            for _ in 0..(self.width * self.height) {
-                data.push(r);
-                data.push(g);
-                data.push(b);
-                data.push(255);
+            // Render the synthetic frame shown at `ticks` straight at the requested size
+            let _ = (field_order, target);
+            let frame = crate::time::ticks_to_frame(ticks, self.frame_rate);
+            let data = self.source.render(frame, spec.width, spec.height);
+            Ok(VideoFrame::from_rgba(spec.width, spec.height, data))
+        }
+    }
+}
+
+#[cfg(feature = "real_ffmpeg")]
+impl StreamDecoder {
+    /// Index of the decoded frame shown at `target`, if it is known yet.
+    fn lookup(&self, target: i64) -> Option<usize> {
+        let idx = self.gop.partition_point(|f| f.pts <= target);
+        if idx == 0 {
+            return None;
+        }
+        let frame = &self.gop[idx - 1];
+        // Without a successor we only know the frame is right if it still
+        // covers the target or nothing follows it.
+        if idx < self.gop.len() || self.eof || target < frame.pts + self.frame_duration {
+            Some(idx - 1)
+        } else {
+            None
+        }
+    }
+
+    fn seek(&mut self, target: i64) -> MediaResult<()> {
+        let key = self.index.as_ref().and_then(|i| i.keyframe_before(target));
+        let sought = match key {
+            Some(key) => self.seek_to_keyframe(key),
+            None => {
+                // Format-level seeks take AV_TIME_BASE (microsecond) units.
+                let seek_us = (i128::from(target) * i128::from(self.time_base.num) * 1_000_000
+                    / i128::from(self.time_base.den)) as i64;
+                self.input.seek(seek_us, ..seek_us)
+            }
+        };
+        sought.map_err(|e| MediaError::Other {
+            path: self.path.clone(),
+            reason: format!("Seek failed: {}", e),
+        })?;
+        self.decoder.flush();
+        self.gop.clear();
+        self.draining = false;
+        self.eof = false;
+        Ok(())
+    }
+
+    /// Positions the demuxer on a keyframe known from the index: by byte offset
+    /// where the format allows it, otherwise by its exact timestamp.
+    fn seek_to_keyframe(&mut self, key: crate::index::Packet) -> Result<(), ffmpeg::Error> {
+        use ffmpeg::ffi::{
+            AVFMT_NO_BYTE_SEEK, AVSEEK_FLAG_BACKWARD, AVSEEK_FLAG_BYTE, av_seek_frame,
+        };
+
+        let stream = self.stream_index as i32;
+        // SAFETY: the format context is owned by `self.input` and stays valid.
+        unsafe {
+            let ctx = self.input.as_mut_ptr();
+            let byte_seek =
+                key.pos >= 0 && (*(*ctx).iformat).flags & AVFMT_NO_BYTE_SEEK as i32 == 0;
+            if byte_seek && av_seek_frame(ctx, stream, key.pos, AVSEEK_FLAG_BYTE as i32) >= 0 {
+                return Ok(());
+            }
+            match av_seek_frame(ctx, stream, key.pts, AVSEEK_FLAG_BACKWARD as i32) {
+                ret if ret < 0 => Err(ffmpeg::Error::from(ret)),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    /// Decodes the next frame into the GOP buffer. Returns `false` at end of stream.
+    fn decode_next(&mut self) -> MediaResult<bool> {
+        let stream_index = self.stream_index;
+        loop {
+            let mut decoded = ffmpeg::util::frame::Video::empty();
+            match self.decoder.receive_frame(&mut decoded) {
+                Ok(()) => {
+                    let Some(pts) = decoded.timestamp().or(decoded.pts()) else {
+                        continue;
+                    };
+                    if decoded.is_key() || self.gop.len() >= MAX_GOP_FRAMES {
+                        // A new GOP starts (or the buffer is full); older frames
+                        // are only reachable through a seek from now on. The last
+                        // one is kept since it may still be the answer.
+                        let keep_from = if decoded.is_key() {
+                            self.gop.len().saturating_sub(1)
+                        } else {
+                            self.gop.len() / 2
+                        };
+                        self.gop.drain(..keep_from);
+                    }
+                    self.gop.push(DecodedFrame {
+                        pts,
+                        frame: decoded,
+                    });
+                    return Ok(true);
+                }
+                Err(ffmpeg::Error::Eof) => {
+                    self.eof = true;
+                    return Ok(false);
+                }
+                Err(ffmpeg::Error::Other { errno }) if errno == ffmpeg::error::EAGAIN => {}
+                Err(e) => return Err(MediaError::from_ffmpeg(&self.path, e)),
+            }
+
+            if self.draining {
+                self.eof = true;
+                return Ok(false);
+            }
+            match self
+                .input
+                .packets()
+                .find(|(stream, _)| stream.index() == stream_index)
+            {
+                Some((_, packet)) => {
+                    self.decoder
+                        .send_packet(&packet)
+                        .map_err(|e| MediaError::from_ffmpeg(&self.path, e))?;
+                }
+                None => {
+                    self.decoder
+                        .send_eof()
+                        .map_err(|e| MediaError::from_ffmpeg(&self.path, e))?;
+                    self.draining = true;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "real_ffmpeg")]
+fn to_rational(r: ffmpeg::Rational) -> Rational {
+    Rational::new(i64::from(r.numerator()), i64::from(r.denominator()))
+}
+
+#[cfg(feature = "real_ffmpeg")]
+fn to_pixel(format: crate::frame::FrameFormat) -> ffmpeg::format::Pixel {
+    use crate::frame::FrameFormat;
+    use ffmpeg::format::Pixel;
+    match format {
+        FrameFormat::Rgba8 => Pixel::RGBA,
+        FrameFormat::Nv12 => Pixel::NV12,
+        FrameFormat::Yuv420p => Pixel::YUV420P,
+        FrameFormat::P010 => Pixel::P010LE,
+        FrameFormat::Rgba16 => Pixel::RGBA64LE,
+        FrameFormat::RgbaF32 => unreachable!("float frames are converted from RGBA64"),
+    }
+}
+
+/// Whether `format` holds RGB rather than YUV, and whether its samples are
+/// deeper than 8 bits.
+#[cfg(feature = "real_ffmpeg")]
+fn pixel_traits(format: ffmpeg::format::Pixel) -> (bool, bool) {
+    // SAFETY: av_pix_fmt_desc_get returns null or a pointer to a static descriptor.
+    unsafe {
+        let desc = ffmpeg::ffi::av_pix_fmt_desc_get(format.into());
+        if desc.is_null() {
+            return (false, false);
+        }
+        let rgb = (*desc).flags & u64::from(ffmpeg::ffi::AV_PIX_FMT_FLAG_RGB) != 0;
+        (rgb, (*desc).comp[0].depth > 8)
+    }
+}
+
+/// Output spec plus the swscale colourspace table and full-range flag of the
+/// source and destination.
+#[cfg(feature = "real_ffmpeg")]
+type ScalerKey = (FrameSpec, (i32, bool), (i32, bool));
+
+/// swscale coefficient table for `matrix`.
+#[cfg(feature = "real_ffmpeg")]
+fn sws_colorspace(matrix: crate::color::Matrix) -> i32 {
+    use crate::color::Matrix;
+    use ffmpeg::ffi;
+
+    (match matrix {
+        Matrix::Bt601 => ffi::SWS_CS_ITU601,
+        Matrix::Bt709 => ffi::SWS_CS_ITU709,
+        Matrix::Bt2020 => ffi::SWS_CS_BT2020,
+        Matrix::Rgb => ffi::SWS_CS_DEFAULT,
+    }) as i32
+}
+
+/// Produces a tightly packed copy of `frame` at the size in `spec`, going
+/// through swscale only when the layout or size differs from the decoder's.
+/// See [`StreamDecoder::decode_frame`] for the layout returned.
+#[cfg(feature = "real_ffmpeg")]
+fn convert_frame(
+    scaler: &mut Option<(ScalerKey, ffmpeg::software::scaling::Context)>,
+    frame: &ffmpeg::util::frame::Video,
+    spec: FrameSpec,
+    field_order: Option<FieldOrder>,
+    target: &ColorInfo,
+) -> std::result::Result<VideoFrame, ffmpeg::Error> {
+    use crate::{
+        color::{Conversion, Matrix, Range},
+        frame::FrameFormat,
+    };
+
+    let (is_rgb, deep) = pixel_traits(frame.format());
+    let source = crate::color::from_frame(frame, is_rgb);
+    // swscale handles matrix and range, but a gamut or transfer change needs
+    // linear light. For those, and for float output, swscale only scales into
+    // a layout that keeps the source encoding and `VideoFrame::convert` does
+    // the rest.
+    let float_path =
+        spec.format == FrameFormat::RgbaF32 || Conversion::new(&source, target).is_some();
+    let format = match spec.format {
+        f if !float_path => f,
+        _ if is_rgb && (deep || spec.format != FrameFormat::Rgba8) => FrameFormat::Rgba16,
+        _ if is_rgb => FrameFormat::Rgba8,
+        f if f.is_rgb() && deep => FrameFormat::P010,
+        f if f.is_rgb() => FrameFormat::Yuv420p,
+        f => f,
+    };
+    let spec = FrameSpec { format, ..spec };
+    // What the output samples are encoded as. swscale does not change the
+    // matrix between two YUV layouts.
+    let color = match (is_rgb, format.is_rgb()) {
+        (false, true) => ColorInfo {
+            matrix: Matrix::Rgb,
+            range: Range::Full,
+            ..source
+        },
+        (true, false) => ColorInfo {
+            matrix: target.matrix,
+            range: target.range,
+            ..source
+        },
+        _ => source,
+    };
+    let key = (
+        spec,
+        (sws_colorspace(source.matrix), source.range == Range::Full),
+        (sws_colorspace(color.matrix), color.range == Range::Full),
+    );
+
+    // Fields are interpolated at full height, before any scaling mixes them.
+    let top_first = match field_order {
+        Some(FieldOrder::Progressive) => None,
+        Some(FieldOrder::TopFirst) => Some(true),
+        Some(FieldOrder::BottomFirst) => Some(false),
+        None => frame.is_interlaced().then(|| frame.is_top_first()),
+    };
+    let deinterlaced;
+    let frame = match top_first {
+        Some(top_first) => {
+            deinterlaced = deinterlace(frame, top_first)?;
+            &deinterlaced
+        }
+        None => frame,
+    };
+
+    let converted;
+    let src = if frame.format() == to_pixel(spec.format)
+        && frame.width() == spec.width
+        && frame.height() == spec.height
+    {
+        frame
+    } else {
+        if !matches!(scaler, Some((k, _)) if *k == key) {
+            let mut ctx = ffmpeg::software::scaling::Context::get(
+                frame.format(),
+                frame.width(),
+                frame.height(),
+                to_pixel(spec.format),
+                spec.width,
+                spec.height,
+                ffmpeg::software::scaling::flag::BILINEAR,
+            )?;
+            let (_, (src_cs, src_full), (dst_cs, dst_full)) = key;
+            // SAFETY: the context is valid and the tables are static.
+            unsafe {
+                ffmpeg::ffi::sws_setColorspaceDetails(
+                    ctx.as_mut_ptr(),
+                    ffmpeg::ffi::sws_getCoefficients(src_cs),
+                    i32::from(src_full),
+                    ffmpeg::ffi::sws_getCoefficients(dst_cs),
+                    i32::from(dst_full),
+                    0,
+                    1 << 16,
+                    1 << 16,
+                );
+            }
+            *scaler = Some((key, ctx));
+        }
+        let (_, ctx) = scaler.as_mut().unwrap();
+        let mut out = ffmpeg::util::frame::Video::empty();
+        ctx.run(frame, &mut out)?;
+        converted = out;
+        &converted
+    };
+
+    // Copy each plane tightly packed
+    let planes = spec
+        .format
+        .plane_sizes(spec.width, spec.height)
+        .into_iter()
+        .enumerate()
+        .map(|(i, (row_bytes, rows))| {
+            let data = src.data(i);
+            let stride = src.stride(i);
+            let mut bytes = Vec::with_capacity(row_bytes * rows);
+            for y in 0..rows {
+                bytes.extend_from_slice(&data[y * stride..y * stride + row_bytes]);
+            }
+            bytes
+        })
+        .collect();
+
+    Ok(VideoFrame {
+        format: spec.format,
+        width: spec.width,
+        height: spec.height,
+        planes,
+        color,
+    })
+}
+
+/// Copy of `frame` with the second field rebuilt from the first by averaging
+/// the lines above and below. The decoder's own buffers may still be
+/// referenced by the codec, so they are never written to.
+#[cfg(feature = "real_ffmpeg")]
+fn deinterlace(
+    frame: &ffmpeg::util::frame::Video,
+    top_first: bool,
+) -> std::result::Result<ffmpeg::util::frame::Video, ffmpeg::Error> {
+    let mut out = ffmpeg::util::frame::Video::new(frame.format(), frame.width(), frame.height());
+    // SAFETY: both frames are valid and `out` was allocated with the same
+    // format and size, which is all av_frame_copy requires.
+    let (wide, chroma_shift) = unsafe {
+        let ret = ffmpeg::ffi::av_frame_copy(out.as_mut_ptr(), frame.as_ptr());
+        if ret < 0 {
+            return Err(ffmpeg::Error::from(ret));
+        }
+        let desc = ffmpeg::ffi::av_pix_fmt_desc_get(frame.format().into());
+        if desc.is_null() {
+            return Err(ffmpeg::Error::InvalidData);
+        }
+        ((*desc).comp[0].depth > 8, u32::from((*desc).log2_chroma_h))
+    };
+    let height = frame.height() as usize;
+    for plane in 0..out.planes() {
+        let stride = out.stride(plane);
+        let rows = if plane == 1 || plane == 2 {
+            height.div_ceil(1 << chroma_shift)
+        } else {
+            height
+        };
+        let data = out.data_mut(plane);
+        // Rebuild every line of the later field.
+        let first = if top_first { 1 } else { 0 };
+        for y in (first..rows).step_by(2) {
+            let above = if y > 0 { y - 1 } else { y + 1 };
+            let below = if y + 1 < rows { y + 1 } else { above };
+            if above >= rows {
+                continue;
+            }
+            for x in (0..stride).step_by(if wide { 2 } else { 1 }) {
+                let (a, b, dst) = (above * stride + x, below * stride + x, y * stride + x);
+                if wide {
+                    let pa = u32::from(u16::from_le_bytes([data[a], data[a + 1]]));
+                    let pb = u32::from(u16::from_le_bytes([data[b], data[b + 1]]));
+                    let avg = ((pa + pb + 1) / 2) as u16;
+                    data[dst..dst + 2].copy_from_slice(&avg.to_le_bytes());
+                } else {
+                    data[dst] = ((u16::from(data[a]) + u16::from(data[b]) + 1) / 2) as u8;
+                }
             }
-            
-            RawImage::from_bytes(
-                PixelFormat::Rgba8Unorm,
-                self.width,
-                self.height,
-                data,
-            )
         }
     }
+    Ok(out)
 }
@@ -0,0 +1,148 @@
+//! Typed decode failures.
+//!
+//! Decoders, the pool and the probe report [`MediaError`] so callers can tell a
+//! missing file from an unsupported codec or a damaged stream. It converts into
+//! `LunarisError` at the `Renderer` boundary; in the world, failures show up as
+//! a [`MediaState`] on the source entity.
+
+use lunaris_api::util::error::LunarisError;
+use std::{fmt, path::Path};
+use timeline::components::MediaState;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MediaError {
+    /// The file is missing or cannot be read.
+    Offline { path: String, reason: String },
+    /// The container, codec or image format cannot be decoded by this build,
+    /// or the file has no stream of the kind asked for.
+    Unsupported { path: String, reason: String },
+    /// The requested time lies past the last frame.
+    EndOfStream { path: String },
+    /// Packets or image data failed to decode.
+    Corrupt { path: String, reason: String },
+    /// Anything else: allocation, scaler setup, seeking.
+    Other { path: String, reason: String },
+}
+
+pub type MediaResult<T> = std::result::Result<T, MediaError>;
+
+impl MediaError {
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Offline { path, .. }
+            | Self::Unsupported { path, .. }
+            | Self::EndOfStream { path }
+            | Self::Corrupt { path, .. }
+            | Self::Other { path, .. } => path,
+        }
+    }
+
+    /// What the failure says about the source as a whole, if anything.
+    /// Corrupt packets and the end of the stream only concern one frame.
+    pub fn media_state(&self) -> Option<MediaState> {
+        match self {
+            Self::Offline { reason, .. } => Some(MediaState::Offline {
+                reason: reason.clone(),
+            }),
+            Self::Unsupported { reason, .. } => Some(MediaState::Unsupported {
+                reason: reason.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Classifies a failure to open or read `path` from the file system.
+    pub fn from_io(path: &Path, e: &std::io::Error) -> Self {
+        let path = path.display().to_string();
+        let reason = e.to_string();
+        match e.kind() {
+            std::io::ErrorKind::NotFound
+            | std::io::ErrorKind::PermissionDenied
+            | std::io::ErrorKind::NotADirectory => Self::Offline { path, reason },
+            _ => Self::Other { path, reason },
+        }
+    }
+
+    /// Classifies an `image` crate failure for `path`.
+    pub fn from_image(path: &Path, e: image::ImageError) -> Self {
+        match e {
+            image::ImageError::IoError(io) => Self::from_io(path, &io),
+            image::ImageError::Unsupported(u) => Self::Unsupported {
+                path: path.display().to_string(),
+                reason: u.to_string(),
+            },
+            image::ImageError::Decoding(d) => Self::Corrupt {
+                path: path.display().to_string(),
+                reason: d.to_string(),
+            },
+            other => Self::Other {
+                path: path.display().to_string(),
+                reason: other.to_string(),
+            },
+        }
+    }
+
+    /// Classifies an FFmpeg failure while opening or decoding `path`.
+    #[cfg(feature = "real_ffmpeg")]
+    pub fn from_ffmpeg(path: &str, e: ffmpeg_next::Error) -> Self {
+        use ffmpeg_next::{Error, error};
+
+        let path = path.to_string();
+        let reason = e.to_string();
+        match e {
+            Error::Other { errno }
+                if errno == error::ENOENT || errno == error::EACCES || errno == error::ENOTDIR =>
+            {
+                Self::Offline { path, reason }
+            }
+            Error::DecoderNotFound
+            | Error::DemuxerNotFound
+            | Error::ProtocolNotFound
+            | Error::StreamNotFound
+            | Error::PatchWelcome => Self::Unsupported { path, reason },
+            Error::Eof => Self::EndOfStream { path },
+            Error::InvalidData | Error::Bug | Error::Bug2 => Self::Corrupt { path, reason },
+            _ => Self::Other { path, reason },
+        }
+    }
+}
+
+impl fmt::Display for MediaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Offline { path, reason } => write!(f, "Media offline: {path}: {reason}"),
+            Self::Unsupported { path, reason } => write!(f, "Unsupported media: {path}: {reason}"),
+            Self::EndOfStream { path } => write!(f, "End of stream: {path}"),
+            Self::Corrupt { path, reason } => write!(f, "Corrupt media: {path}: {reason}"),
+            Self::Other { path, reason } => write!(f, "Decode failed: {path}: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for MediaError {}
+
+/// Only for the `Renderer` boundary: `VideoPlugin::schedule_frame` and
+/// `schedule_audio` hand out the `MediaError` itself, so callers that need to
+/// tell a missing file from an unsupported one match on it there.
+///
+/// Failures tied to what the job asked for come back as `InvalidArgument`
+/// naming the parameter at fault: `path` for a source that is offline or
+/// cannot be decoded, `frame` for a time past the end. Decode trouble inside
+/// a readable file stays `Generic`. The message is the full `MediaError`.
+impl From<MediaError> for LunarisError {
+    fn from(e: MediaError) -> Self {
+        let name = match e {
+            MediaError::Offline { .. } | MediaError::Unsupported { .. } => "path",
+            MediaError::EndOfStream { .. } => "frame",
+            MediaError::Corrupt { .. } | MediaError::Other { .. } => {
+                return LunarisError::Generic {
+                    reason: e.to_string(),
+                };
+            }
+        };
+        LunarisError::InvalidArgument {
+            name: name.to_string(),
+            reason: Some(e.to_string()),
+        }
+    }
+}
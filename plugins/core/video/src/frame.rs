@@ -0,0 +1,460 @@
+//! Decoded frames in their native layout.
+//!
+//! Frames stay planar YUV through the plugin when the consumer asks for it.
+//! `RawImage` only carries RGBA, so conversion happens once, in
+//! [`VideoFrame::into_raw_image`], when a frame is handed to compositing or display.
+//! Every frame carries its [`ColorInfo`]; layout and colour conversions share
+//! one pass through floating-point RGB.
+
+use crate::color::{ColorInfo, Conversion, Matrix, Range};
+use lunaris_api::{
+    render::{PixelFormat, RawImage},
+    util::error::Result,
+};
+
+/// Pixel layouts a decoded frame can be kept in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum FrameFormat {
+    /// Packed 8-bit RGBA, one plane.
+    #[default]
+    Rgba8,
+    /// 8-bit Y plane followed by an interleaved half-resolution CbCr plane.
+    Nv12,
+    /// 8-bit Y, Cb and Cr planes, chroma at half resolution.
+    Yuv420p,
+    /// As NV12 with 16-bit little-endian samples holding 10 bits in the high bits.
+    P010,
+    /// Packed RGBA, 16-bit little-endian unsigned normalised channels.
+    Rgba16,
+    /// Packed RGBA, 32-bit little-endian float channels; values may exceed 1.0.
+    RgbaF32,
+}
+
+impl FrameFormat {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "rgba" | "rgba8" => Some(Self::Rgba8),
+            "nv12" => Some(Self::Nv12),
+            "yuv420p" | "i420" => Some(Self::Yuv420p),
+            "p010" | "p010le" => Some(Self::P010),
+            "rgba16" | "rgba64" => Some(Self::Rgba16),
+            "rgbaf32" | "rgba32f" => Some(Self::RgbaF32),
+            _ => None,
+        }
+    }
+
+    pub fn is_rgb(self) -> bool {
+        matches!(self, Self::Rgba8 | Self::Rgba16 | Self::RgbaF32)
+    }
+
+    /// Row length in bytes and row count of each plane for a `width`x`height` frame.
+    pub fn plane_sizes(self, width: u32, height: u32) -> Vec<(usize, usize)> {
+        let (w, h) = (width as usize, height as usize);
+        let (cw, ch) = (w.div_ceil(2), h.div_ceil(2));
+        match self {
+            Self::Rgba8 => vec![(w * 4, h)],
+            Self::Nv12 => vec![(w, h), (cw * 2, ch)],
+            Self::Yuv420p => vec![(w, h), (cw, ch), (cw, ch)],
+            Self::P010 => vec![(w * 2, h), (cw * 4, ch)],
+            Self::Rgba16 => vec![(w * 8, h)],
+            Self::RgbaF32 => vec![(w * 16, h)],
+        }
+    }
+}
+
+/// Layout and resolution a frame is decoded into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FrameSpec {
+    pub format: FrameFormat,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Requested reduction of the source resolution, e.g. for scrubbing previews.
+/// Frames are never upscaled and keep the source aspect ratio.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Downscale {
+    /// Uniform factor in `0.0..=1.0`.
+    pub scale: Option<f64>,
+    pub max_width: Option<u32>,
+    pub max_height: Option<u32>,
+}
+
+impl Downscale {
+    /// Output size for a `width`x`height` source. Dimensions are rounded to
+    /// even numbers so 4:2:0 layouts stay valid.
+    pub fn apply(&self, width: u32, height: u32) -> (u32, u32) {
+        let mut factor = self.scale.unwrap_or(1.0).clamp(0.0, 1.0);
+        if let Some(max_w) = self.max_width {
+            factor = factor.min(f64::from(max_w) / f64::from(width.max(1)));
+        }
+        if let Some(max_h) = self.max_height {
+            factor = factor.min(f64::from(max_h) / f64::from(height.max(1)));
+        }
+        if factor >= 1.0 {
+            return (width, height);
+        }
+        let even = |v: u32| ((f64::from(v) * factor / 2.0).round() as u32 * 2).max(2);
+        (even(width), even(height))
+    }
+}
+
+/// A decoded frame with tightly packed planes laid out as in [`FrameFormat::plane_sizes`].
+#[derive(Debug, Clone)]
+pub struct VideoFrame {
+    pub format: FrameFormat,
+    pub width: u32,
+    pub height: u32,
+    pub planes: Vec<Vec<u8>>,
+    pub color: ColorInfo,
+}
+
+impl VideoFrame {
+    /// An 8-bit RGBA frame in BT.709.
+    pub fn from_rgba(width: u32, height: u32, data: Vec<u8>) -> Self {
+        Self {
+            format: FrameFormat::Rgba8,
+            width,
+            height,
+            planes: vec![data],
+            color: ColorInfo::default(),
+        }
+    }
+
+    pub fn byte_len(&self) -> usize {
+        self.planes.iter().map(Vec::len).sum()
+    }
+
+    /// Converts to RGBA (if needed) for handing to the compositor. 10-bit and
+    /// 16-bit frames keep their precision, float frames their range. The
+    /// pixels stay in the frame's colour space; decoded frames are already in
+    /// the working space.
+    pub fn into_raw_image(self) -> Result<RawImage> {
+        let (width, height) = (self.width, self.height);
+        let (format, pixel_format) = match self.format {
+            FrameFormat::P010 | FrameFormat::Rgba16 => {
+                (FrameFormat::Rgba16, PixelFormat::Rgba16Unorm)
+            }
+            FrameFormat::RgbaF32 => (FrameFormat::RgbaF32, PixelFormat::Rgba32Float),
+            _ => (FrameFormat::Rgba8, PixelFormat::Rgba8Unorm),
+        };
+        let rgba = self.into_format(format);
+        let data = rgba.planes.into_iter().next().unwrap_or_default();
+        RawImage::from_bytes(pixel_format, width, height, data)
+    }
+
+    /// Converts between layouts, keeping the colour space. Returns `self`
+    /// untouched if it is already in `format`.
+    pub fn into_format(self, format: FrameFormat) -> Self {
+        let color = self.color;
+        self.convert(format, &color)
+    }
+
+    /// Converts to `format` with samples meaning `color`, in one pass.
+    /// HDR metadata is kept only if the target is HDR too.
+    ///
+    /// Returns `self` retagged when nothing but the tags differ. Anything else
+    /// goes through float RGB per pixel, so decoders leave this to gamut and
+    /// transfer changes and let swscale do layout, matrix and range.
+    pub fn convert(self, format: FrameFormat, color: &ColorInfo) -> Self {
+        let conversion = Conversion::new(&self.color, color);
+        let same_encoding = format.is_rgb() || yuv_encoding(&self.color) == yuv_encoding(color);
+        let color = ColorInfo {
+            hdr: if color.is_hdr() { self.color.hdr } else { None },
+            ..*color
+        };
+        if self.format == format && conversion.is_none() && same_encoding {
+            return Self { color, ..self };
+        }
+        let mut pixels = self.rgb_f32();
+        if let Some(conversion) = conversion {
+            for p in &mut pixels {
+                let [r, g, b] = conversion.apply([p[0], p[1], p[2]]);
+                *p = [r, g, b, p[3]];
+            }
+        }
+        Self::from_rgb_f32(self.width, self.height, &pixels, format, color)
+    }
+
+    /// Rotates clockwise by `degrees`, a multiple of 90. Quarter turns swap
+    /// width and height; 4:2:0 layouts need even dimensions to stay exact.
+    pub fn rotated(self, degrees: i32) -> Self {
+        let turns = degrees.rem_euclid(360) / 90;
+        if turns == 0 {
+            return self;
+        }
+        let (w, h) = (self.width, self.height);
+        let (new_w, new_h) = if turns % 2 == 1 { (h, w) } else { (w, h) };
+        let sizes = self.format.plane_sizes(w, h);
+        let planes = self
+            .planes
+            .iter()
+            .zip(sizes)
+            .enumerate()
+            .map(|(i, (plane, (row_bytes, rows)))| {
+                // Chroma planes are half width; a sample may span several bytes.
+                let cols = (if i == 0 { w } else { w.div_ceil(2) }) as usize;
+                let elem = row_bytes / cols.max(1);
+                let mut out = vec![0u8; plane.len()];
+                for y in 0..rows {
+                    for x in 0..cols {
+                        let (dx, dy, dst_cols) = match turns {
+                            1 => (rows - 1 - y, x, rows),
+                            2 => (cols - 1 - x, rows - 1 - y, cols),
+                            _ => (y, cols - 1 - x, rows),
+                        };
+                        let src = (y * cols + x) * elem;
+                        let dst = (dy * dst_cols + dx) * elem;
+                        out[dst..dst + elem].copy_from_slice(&plane[src..src + elem]);
+                    }
+                }
+                out
+            })
+            .collect();
+        Self {
+            format: self.format,
+            width: new_w,
+            height: new_h,
+            planes,
+            color: self.color,
+        }
+    }
+
+    /// Normalised (Y, Cb, Cr) at pixel `(x, y)`; Y in 0..1, chroma in -0.5..0.5.
+    fn yuv_at(&self, x: usize, y: usize) -> (f32, f32, f32) {
+        let w = self.width as usize;
+        let cw = w.div_ceil(2);
+        let (cx, cy) = (x / 2, y / 2);
+        let range = self.color.range;
+        let (luma, chroma) = (
+            |v: u8| norm_luma(f32::from(v), 8, range),
+            |v: u8| norm_chroma(f32::from(v), 8, range),
+        );
+        let (luma10, chroma10) = (
+            |v: u16| norm_luma(f32::from(v >> 6), 10, range),
+            |v: u16| norm_chroma(f32::from(v >> 6), 10, range),
+        );
+        match self.format {
+            FrameFormat::Nv12 => (
+                luma(self.planes[0][y * w + x]),
+                chroma(self.planes[1][cy * cw * 2 + cx * 2]),
+                chroma(self.planes[1][cy * cw * 2 + cx * 2 + 1]),
+            ),
+            FrameFormat::Yuv420p => (
+                luma(self.planes[0][y * w + x]),
+                chroma(self.planes[1][cy * cw + cx]),
+                chroma(self.planes[2][cy * cw + cx]),
+            ),
+            FrameFormat::P010 => (
+                luma10(read_u16(&self.planes[0], (y * w + x) * 2)),
+                chroma10(read_u16(&self.planes[1], (cy * cw + cx) * 4)),
+                chroma10(read_u16(&self.planes[1], (cy * cw + cx) * 4 + 2)),
+            ),
+            FrameFormat::Rgba8 | FrameFormat::Rgba16 | FrameFormat::RgbaF32 => {
+                unreachable!("RGBA frames have no YUV samples")
+            }
+        }
+    }
+
+    /// Non-linear RGBA of every pixel, row by row.
+    fn rgb_f32(&self) -> Vec<[f32; 4]> {
+        let (w, h) = (self.width as usize, self.height as usize);
+        let src = &self.planes[0];
+        match self.format {
+            FrameFormat::Rgba8 => src
+                .chunks_exact(4)
+                .map(|p| p.map(|c| f32::from(c) / 255.0))
+                .collect(),
+            FrameFormat::Rgba16 => src
+                .chunks_exact(8)
+                .map(|p| std::array::from_fn(|i| f32::from(read_u16(p, i * 2)) / 65535.0))
+                .collect(),
+            FrameFormat::RgbaF32 => src
+                .chunks_exact(16)
+                .map(|p| {
+                    std::array::from_fn(|i| {
+                        f32::from_le_bytes([p[i * 4], p[i * 4 + 1], p[i * 4 + 2], p[i * 4 + 3]])
+                    })
+                })
+                .collect(),
+            FrameFormat::Nv12 | FrameFormat::Yuv420p | FrameFormat::P010 => {
+                let (kr, kb) = yuv_encoding(&self.color).0;
+                let mut out = Vec::with_capacity(w * h);
+                for y in 0..h {
+                    for x in 0..w {
+                        let (luma, cb, cr) = self.yuv_at(x, y);
+                        let [r, g, b] = yuv_to_rgb(luma, cb, cr, kr, kb);
+                        out.push([r, g, b, 1.0]);
+                    }
+                }
+                out
+            }
+        }
+    }
+
+    /// Builds a frame in `format` from non-linear RGBA pixels in `color`.
+    fn from_rgb_f32(
+        width: u32,
+        height: u32,
+        pixels: &[[f32; 4]],
+        format: FrameFormat,
+        color: ColorInfo,
+    ) -> Self {
+        let (w, h) = (width as usize, height as usize);
+        let (cw, ch) = (w.div_ceil(2), h.div_ceil(2));
+        let planes = match format {
+            FrameFormat::Rgba8 => vec![pixels.iter().flat_map(|p| p.map(to_u8)).collect()],
+            FrameFormat::Rgba16 => vec![
+                pixels
+                    .iter()
+                    .flat_map(|p| p.map(|c| to_u16(c).to_le_bytes()))
+                    .flatten()
+                    .collect(),
+            ],
+            FrameFormat::RgbaF32 => vec![
+                pixels
+                    .iter()
+                    .flat_map(|p| p.map(f32::to_le_bytes))
+                    .flatten()
+                    .collect(),
+            ],
+            FrameFormat::Nv12 | FrameFormat::Yuv420p | FrameFormat::P010 => {
+                let ((kr, kb), range) = yuv_encoding(&color);
+                let yuv = |x: usize, y: usize| {
+                    let [r, g, b, _] = pixels[y * w + x];
+                    rgb_to_yuv([r, g, b].map(|c| c.clamp(0.0, 1.0)), kr, kb)
+                };
+                let mut luma = vec![0.0f32; w * h];
+                for y in 0..h {
+                    for x in 0..w {
+                        luma[y * w + x] = yuv(x, y).0;
+                    }
+                }
+                // Chroma is the average over each 2x2 block.
+                let mut chroma = vec![(0.0f32, 0.0f32); cw * ch];
+                for cy in 0..ch {
+                    for cx in 0..cw {
+                        let (mut cb, mut cr, mut n) = (0.0, 0.0, 0.0);
+                        for y in (cy * 2)..(cy * 2 + 2).min(h) {
+                            for x in (cx * 2)..(cx * 2 + 2).min(w) {
+                                let (_, b, r) = yuv(x, y);
+                                cb += b;
+                                cr += r;
+                                n += 1.0;
+                            }
+                        }
+                        chroma[cy * cw + cx] = (cb / n, cr / n);
+                    }
+                }
+
+                let (luma8, chroma8) = (
+                    |l: f32| enc_luma(l, 8, range) as u8,
+                    |c: f32| enc_chroma(c, 8, range) as u8,
+                );
+                let (luma10, chroma10) = (
+                    |l: f32| (enc_luma(l, 10, range) << 6).to_le_bytes(),
+                    |c: f32| (enc_chroma(c, 10, range) << 6).to_le_bytes(),
+                );
+                match format {
+                    FrameFormat::Nv12 => vec![
+                        luma.iter().map(|&l| luma8(l)).collect(),
+                        chroma
+                            .iter()
+                            .flat_map(|&(cb, cr)| [chroma8(cb), chroma8(cr)])
+                            .collect(),
+                    ],
+                    FrameFormat::Yuv420p => vec![
+                        luma.iter().map(|&l| luma8(l)).collect(),
+                        chroma.iter().map(|&(cb, _)| chroma8(cb)).collect(),
+                        chroma.iter().map(|&(_, cr)| chroma8(cr)).collect(),
+                    ],
+                    _ => vec![
+                        luma.iter().flat_map(|&l| luma10(l)).collect(),
+                        chroma
+                            .iter()
+                            .flat_map(|&(cb, cr)| {
+                                let [a, b] = chroma10(cb);
+                                let [c, d] = chroma10(cr);
+                                [a, b, c, d]
+                            })
+                            .collect(),
+                    ],
+                }
+            }
+        };
+        Self {
+            format,
+            width,
+            height,
+            planes,
+            color,
+        }
+    }
+}
+
+/// `(Kr, Kb)` and range used for YUV samples described by `color`. RGB
+/// sources put into a YUV layout are encoded as BT.709.
+fn yuv_encoding(color: &ColorInfo) -> ((f32, f32), Range) {
+    match color.matrix.coefficients() {
+        Some(k) => (k, color.range),
+        None => (Matrix::Bt709.coefficients().unwrap(), color.range),
+    }
+}
+
+fn rgb_to_yuv([r, g, b]: [f32; 3], kr: f32, kb: f32) -> (f32, f32, f32) {
+    let y = kr * r + (1.0 - kr - kb) * g + kb * b;
+    (
+        y,
+        (b - y) / (2.0 * (1.0 - kb)),
+        (r - y) / (2.0 * (1.0 - kr)),
+    )
+}
+
+fn yuv_to_rgb(y: f32, cb: f32, cr: f32, kr: f32, kb: f32) -> [f32; 3] {
+    let r = y + 2.0 * (1.0 - kr) * cr;
+    let b = y + 2.0 * (1.0 - kb) * cb;
+    let g = (y - kr * r - kb * b) / (1.0 - kr - kb);
+    [r, g, b]
+}
+
+// Code values of `bits`-deep samples. Limited range scales the 8-bit
+// 16-235 (luma) and 16-240 (chroma) levels; full range uses every code.
+fn norm_luma(code: f32, bits: u32, range: Range) -> f32 {
+    let scale = (1u32 << (bits - 8)) as f32;
+    match range {
+        Range::Limited => (code - 16.0 * scale) / (219.0 * scale),
+        Range::Full => code / ((1u32 << bits) - 1) as f32,
+    }
+}
+fn norm_chroma(code: f32, bits: u32, range: Range) -> f32 {
+    let scale = (1u32 << (bits - 8)) as f32;
+    match range {
+        Range::Limited => (code - 128.0 * scale) / (224.0 * scale),
+        Range::Full => (code - 128.0 * scale) / ((1u32 << bits) - 1) as f32,
+    }
+}
+fn enc_luma(y: f32, bits: u32, range: Range) -> u16 {
+    let (scale, max) = ((1u32 << (bits - 8)) as f32, ((1u32 << bits) - 1) as f32);
+    let code = match range {
+        Range::Limited => (16.0 + y * 219.0) * scale,
+        Range::Full => y * max,
+    };
+    code.round().clamp(0.0, max) as u16
+}
+fn enc_chroma(c: f32, bits: u32, range: Range) -> u16 {
+    let (scale, max) = ((1u32 << (bits - 8)) as f32, ((1u32 << bits) - 1) as f32);
+    let code = match range {
+        Range::Limited => (128.0 + c * 224.0) * scale,
+        Range::Full => 128.0 * scale + c * max,
+    };
+    code.round().clamp(0.0, max) as u16
+}
+fn read_u16(plane: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([plane[at], plane[at + 1]])
+}
+fn to_u8(v: f32) -> u8 {
+    (v * 255.0).round().clamp(0.0, 255.0) as u8
+}
+fn to_u16(v: f32) -> u16 {
+    (v * 65535.0).round().clamp(0.0, 65535.0) as u16
+}
@@ -0,0 +1,129 @@
+//! How stored pixels map to the picture the viewer should see: field order,
+//! display rotation and pixel aspect ratio.
+//!
+//! Decoders and the probe report what the stream says; the override components
+//! next to a `VideoSource` replace individual values for sources that are
+//! flagged wrongly.
+
+use crate::{color::WorkingSpace, time::Rational};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum FieldOrder {
+    #[default]
+    Progressive,
+    /// Interlaced, top field shown first.
+    TopFirst,
+    /// Interlaced, bottom field shown first (DV).
+    BottomFirst,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Geometry {
+    pub field_order: FieldOrder,
+    /// Clockwise rotation in degrees: 0, 90, 180 or 270.
+    pub rotation: i32,
+    /// Width of a stored pixel relative to its height.
+    pub pixel_aspect: Rational,
+}
+
+impl Default for Geometry {
+    fn default() -> Self {
+        Self {
+            field_order: FieldOrder::Progressive,
+            rotation: 0,
+            pixel_aspect: Rational::new(1, 1),
+        }
+    }
+}
+
+/// Per-source replacements for what the stream reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Overrides {
+    pub field_order: Option<FieldOrder>,
+    pub rotation: Option<i32>,
+    pub pixel_aspect: Option<Rational>,
+}
+
+/// What happens to a decoded frame besides scaling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FrameView {
+    /// Clockwise rotation in degrees, applied last.
+    pub rotation: i32,
+    /// Forced field order. `None` deinterlaces frames the codec flags as interlaced;
+    /// `Some(Progressive)` never deinterlaces.
+    pub field_order: Option<FieldOrder>,
+    /// Colour space the frame is converted into.
+    pub working: WorkingSpace,
+}
+
+impl Geometry {
+    pub fn with_overrides(self, overrides: &Overrides) -> Self {
+        Self {
+            field_order: overrides.field_order.unwrap_or(self.field_order),
+            rotation: normalize_rotation(overrides.rotation.unwrap_or(self.rotation)),
+            pixel_aspect: overrides
+                .pixel_aspect
+                .filter(Rational::is_valid)
+                .unwrap_or(self.pixel_aspect),
+        }
+    }
+
+    /// Picture size for `width`x`height` stored pixels: stretched to square
+    /// pixels horizontally, then rotated.
+    pub fn display_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let sar = self.pixel_aspect;
+        let width = if sar.is_valid() && sar.num != sar.den {
+            let w = (i64::from(width) * sar.num + sar.den / 2) / sar.den;
+            (((w + 1) / 2) * 2).clamp(2, i64::from(u32::MAX)) as u32
+        } else {
+            width
+        };
+        if self.rotation.rem_euclid(180) == 90 {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    /// The view for decoding with these values; `overrides` decide whether the
+    /// field order is forced or left to the per-frame flags.
+    pub fn view(&self, overrides: &Overrides) -> FrameView {
+        FrameView {
+            rotation: self.rotation,
+            field_order: overrides.field_order,
+            ..FrameView::default()
+        }
+    }
+}
+
+/// Snaps to the nearest quarter turn in `0..360`.
+pub fn normalize_rotation(degrees: i32) -> i32 {
+    ((degrees as f64 / 90.0).round() as i32 * 90).rem_euclid(360)
+}
+
+/// Field order, rotation and sample aspect ratio of a video stream.
+#[cfg(feature = "real_ffmpeg")]
+pub fn from_stream(stream: &ffmpeg_next::format::stream::Stream) -> Geometry {
+    use ffmpeg_next::ffi::AVFieldOrder;
+
+    // SAFETY: the parameters are owned by the stream, which outlives this call.
+    let (field_order, sar) = unsafe {
+        let par = &*stream.parameters().as_ptr();
+        (par.field_order, par.sample_aspect_ratio)
+    };
+    let field_order = match field_order {
+        AVFieldOrder::AV_FIELD_TT | AVFieldOrder::AV_FIELD_TB => FieldOrder::TopFirst,
+        AVFieldOrder::AV_FIELD_BB | AVFieldOrder::AV_FIELD_BT => FieldOrder::BottomFirst,
+        _ => FieldOrder::Progressive,
+    };
+    let pixel_aspect = Rational::new(i64::from(sar.num), i64::from(sar.den));
+    Geometry {
+        field_order,
+        rotation: normalize_rotation(crate::probe::rotation(stream)),
+        pixel_aspect: if pixel_aspect.is_valid() {
+            pixel_aspect
+        } else {
+            Rational::new(1, 1)
+        },
+    }
+}
@@ -0,0 +1,263 @@
+//! Still images and numbered image sequences as frame sources.
+//!
+//! A path whose file name holds a printf-style counter (`shot_%04d.exr`,
+//! `frame_%d.png`) is an image sequence; any other path with an image
+//! extension is a still that is shown for every frame. Both decode without
+//! FFmpeg.
+//!
+//! Sequences have no timing of their own. They play at [`DEFAULT_IMAGE_RATE`]
+//! unless the render job sets `sequence_rate`, starting at the lowest frame
+//! number on disk. Gaps in the numbering hold the previous frame.
+
+use crate::{
+    color::ColorInfo,
+    error::{MediaError, MediaResult},
+    frame::{FrameFormat, FrameSpec, VideoFrame},
+    time::Rational,
+};
+use image::{DynamicImage, imageops::FilterType};
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+/// Frame rate of image sequences when the job does not give one.
+pub const DEFAULT_IMAGE_RATE: Rational = Rational::new(24, 1);
+
+const IMAGE_EXTENSIONS: [&str; 5] = ["png", "jpg", "jpeg", "qoi", "exr"];
+
+/// Whether `path` names a still image or an image sequence rather than a container.
+pub fn is_image_path(path: &Path) -> bool {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .is_some_and(|e| IMAGE_EXTENSIONS.contains(&e.as_str()))
+}
+
+/// Counter placeholder in a sequence file name: `prefix%0Nd suffix`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SequencePattern {
+    prefix: String,
+    suffix: String,
+    /// Zero-padded width, 0 for unpadded numbers.
+    digits: usize,
+}
+
+impl SequencePattern {
+    fn parse(file_name: &str) -> Option<Self> {
+        let start = file_name.find('%')?;
+        let rest = &file_name[start + 1..];
+        let end = rest.find('d')?;
+        let spec = &rest[..end];
+        let digits = match spec {
+            "" => 0,
+            _ if spec.starts_with('0') => spec[1..].parse().ok()?,
+            _ => return None,
+        };
+        Some(Self {
+            prefix: file_name[..start].to_string(),
+            suffix: rest[end + 1..].to_string(),
+            digits,
+        })
+    }
+
+    fn file_name(&self, number: u64) -> String {
+        format!(
+            "{}{:0width$}{}",
+            self.prefix,
+            number,
+            self.suffix,
+            width = self.digits
+        )
+    }
+
+    /// Frame number of `file_name`, if it belongs to the sequence.
+    fn number(&self, file_name: &str) -> Option<u64> {
+        let digits = file_name
+            .strip_prefix(&self.prefix)?
+            .strip_suffix(&self.suffix)?;
+        if digits.is_empty()
+            || !digits.bytes().all(|b| b.is_ascii_digit())
+            || (self.digits > 0 && digits.len() < self.digits)
+        {
+            return None;
+        }
+        digits.parse().ok()
+    }
+}
+
+enum ImageKind {
+    Still(PathBuf),
+    Sequence {
+        dir: PathBuf,
+        pattern: SequencePattern,
+        /// Frame numbers present on disk.
+        numbers: BTreeSet<u64>,
+    },
+}
+
+pub struct ImageSource {
+    kind: ImageKind,
+    width: u32,
+    height: u32,
+    frame_rate: Rational,
+    /// Last image read, full size, with its frame number. Stills are read once.
+    cached: Option<(u64, DynamicImage)>,
+}
+
+impl ImageSource {
+    /// Opens a still or, if the file name holds a `%d` counter, scans its
+    /// directory for the frames of the sequence.
+    pub fn open(path: &Path) -> MediaResult<Self> {
+        let file_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let kind = match SequencePattern::parse(&file_name) {
+            Some(pattern) => {
+                let dir = path
+                    .parent()
+                    .filter(|p| !p.as_os_str().is_empty())
+                    .unwrap_or_else(|| Path::new("."))
+                    .to_path_buf();
+                let entries = std::fs::read_dir(&dir).map_err(|e| MediaError::from_io(path, &e))?;
+                let numbers: BTreeSet<u64> = entries
+                    .filter_map(|e| e.ok())
+                    .filter_map(|e| pattern.number(&e.file_name().to_string_lossy()))
+                    .collect();
+                if numbers.is_empty() {
+                    return Err(MediaError::Offline {
+                        path: path.display().to_string(),
+                        reason: "No frames of the sequence found".to_string(),
+                    });
+                }
+                ImageKind::Sequence {
+                    dir,
+                    pattern,
+                    numbers,
+                }
+            }
+            None => ImageKind::Still(path.to_path_buf()),
+        };
+        let mut source = Self {
+            kind,
+            width: 0,
+            height: 0,
+            frame_rate: DEFAULT_IMAGE_RATE,
+            cached: None,
+        };
+        let first = source.file(0);
+        (source.width, source.height) =
+            image::image_dimensions(&first).map_err(|e| MediaError::from_image(&first, e))?;
+        Ok(source)
+    }
+
+    pub fn frame_rate(&self) -> Rational {
+        self.frame_rate
+    }
+
+    /// Sets the rate sequence frames are played back at.
+    pub fn set_frame_rate(&mut self, rate: Rational) {
+        if rate.is_valid() {
+            self.frame_rate = rate;
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Number of frames, counting gaps in the numbering; 1 for stills.
+    pub fn frame_count(&self) -> u64 {
+        match &self.kind {
+            ImageKind::Still(_) => 1,
+            ImageKind::Sequence { numbers, .. } => {
+                numbers.last().unwrap() - numbers.first().unwrap() + 1
+            }
+        }
+    }
+
+    /// Reads the image shown at `ticks` and scales it to the size in `spec`.
+    ///
+    /// The frame keeps the image's depth: float images (EXR) come back as
+    /// scene-linear [`FrameFormat::RgbaF32`], 16-bit ones as
+    /// [`FrameFormat::Rgba16`], everything else as 8-bit sRGB.
+    pub fn decode_frame(&mut self, ticks: u64, spec: FrameSpec) -> MediaResult<VideoFrame> {
+        let number = self.number_at(crate::time::ticks_to_frame(ticks, self.frame_rate));
+        if !matches!(&self.cached, Some((n, _)) if *n == number) {
+            let file = self.file(number);
+            let image = image::open(&file).map_err(|e| MediaError::from_image(&file, e))?;
+            self.cached = Some((number, image));
+        }
+        let (_, image) = self.cached.as_ref().unwrap();
+
+        let (width, height) = (spec.width, spec.height);
+        let resize = (image.width(), image.height()) != (width, height);
+        let (format, color, data) = match image {
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+                let mut buffer = image.to_rgba32f();
+                if resize {
+                    buffer = image::imageops::resize(&buffer, width, height, FilterType::Triangle);
+                }
+                let data = buffer.into_raw().into_iter().flat_map(f32::to_le_bytes);
+                (FrameFormat::RgbaF32, ColorInfo::LINEAR, data.collect())
+            }
+            DynamicImage::ImageLuma16(_)
+            | DynamicImage::ImageLumaA16(_)
+            | DynamicImage::ImageRgb16(_)
+            | DynamicImage::ImageRgba16(_) => {
+                let mut buffer = image.to_rgba16();
+                if resize {
+                    buffer = image::imageops::resize(&buffer, width, height, FilterType::Triangle);
+                }
+                let data = buffer.into_raw().into_iter().flat_map(u16::to_le_bytes);
+                (FrameFormat::Rgba16, ColorInfo::SRGB, data.collect())
+            }
+            _ => {
+                let mut buffer = image.to_rgba8();
+                if resize {
+                    buffer = image::imageops::resize(&buffer, width, height, FilterType::Triangle);
+                }
+                (FrameFormat::Rgba8, ColorInfo::SRGB, buffer.into_raw())
+            }
+        };
+        Ok(VideoFrame {
+            format,
+            width,
+            height,
+            planes: vec![data],
+            color,
+        })
+    }
+
+    /// Frame number on disk shown for sequence frame `index`: the latest one at
+    /// or before it, held past the end of the sequence.
+    fn number_at(&self, index: u64) -> u64 {
+        match &self.kind {
+            ImageKind::Still(_) => 0,
+            ImageKind::Sequence { numbers, .. } => {
+                let first = *numbers.first().unwrap();
+                let wanted = first.saturating_add(index);
+                numbers
+                    .range(..=wanted)
+                    .next_back()
+                    .copied()
+                    .unwrap_or(first)
+            }
+        }
+    }
+
+    /// File holding frame `number`; the first frame for `0` on a sequence.
+    fn file(&self, number: u64) -> PathBuf {
+        match &self.kind {
+            ImageKind::Still(path) => path.clone(),
+            ImageKind::Sequence {
+                dir,
+                pattern,
+                numbers,
+            } => {
+                let number = number.max(*numbers.first().unwrap());
+                dir.join(pattern.file_name(number))
+            }
+        }
+    }
+}
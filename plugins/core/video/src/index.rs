@@ -0,0 +1,544 @@
+//! Per-source table of video packets and frame presentation times.
+//!
+//! Phones and screen recorders write variable frame rate (VFR) streams, where
+//! "frame n" cannot be derived from the nominal rate. The index lists when
+//! every frame is shown, read from the demuxer without decoding. It also keeps
+//! the file position, timestamp and keyframe flag of every packet, so
+//! decoders seek straight to the keyframe before a frame and the number of
+//! frames to decode from there is known before any work is scheduled.
+//!
+//! The index is built in the background when a source is first probed and
+//! cached on disk in the [`MediaCacheDir`](crate::components::MediaCacheDir),
+//! so reopening a project skips the scan.
+//!
+//! Timeline time maps to source frames through the index, or, for clips with
+//! a [`ConformRate`](crate::components::ConformRate), by treating the frames
+//! as evenly spaced at that rate.
+
+use crate::{
+    error::{MediaError, MediaResult},
+    time::{self, Rational},
+};
+use lunaris_api::consts::tps;
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, mpsc},
+};
+
+/// One video packet as stored in the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Packet {
+    /// Presentation timestamp in the stream time base.
+    pub pts: i64,
+    /// Byte offset in the file; -1 if the demuxer does not report it.
+    pub pos: i64,
+    pub key: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameIndex {
+    /// Time base and first timestamp of the stream the packets belong to.
+    time_base: Rational,
+    start_pts: i64,
+    /// Video packets in file order.
+    packets: Vec<Packet>,
+    /// Indices into `packets` of the keyframes, by ascending pts.
+    keys: Vec<usize>,
+    /// Ticks from the start of the stream at which each frame is shown, ascending.
+    starts: Vec<u64>,
+    /// End of the last frame.
+    end: u64,
+}
+
+impl FrameIndex {
+    fn new(time_base: Rational, start_pts: i64, packets: Vec<Packet>, end: u64) -> Self {
+        let mut pts: Vec<i64> = packets.iter().map(|p| p.pts).collect();
+        // Packets come in decode order; B-frames make that differ from display order.
+        pts.sort_unstable();
+        pts.dedup();
+        let starts = pts
+            .iter()
+            .map(|&p| time::pts_to_ticks(p - start_pts, time_base))
+            .collect();
+        let mut keys: Vec<usize> = (0..packets.len()).filter(|&i| packets[i].key).collect();
+        keys.sort_by_key(|&i| packets[i].pts);
+        Self {
+            time_base,
+            start_pts,
+            packets,
+            keys,
+            starts,
+            end,
+        }
+    }
+
+    pub fn time_base(&self) -> Rational {
+        self.time_base
+    }
+
+    pub fn start_pts(&self) -> i64 {
+        self.start_pts
+    }
+
+    /// The last keyframe at or before `pts`, in the stream time base.
+    pub fn keyframe_before(&self, pts: i64) -> Option<Packet> {
+        let n = self.keys.partition_point(|&i| self.packets[i].pts <= pts);
+        n.checked_sub(1).map(|k| self.packets[self.keys[k]])
+    }
+
+    /// Frames to decode to show the frame at `ticks`, counting from the
+    /// keyframe a seek lands on.
+    pub fn decode_cost(&self, ticks: u64) -> u64 {
+        let frame = self.frame_at(ticks);
+        let pts = self.start_pts + time::ticks_to_pts(ticks, self.time_base);
+        let first = self.keyframe_before(pts).map_or(0, |key| {
+            self.frame_at(time::pts_to_ticks(key.pts - self.start_pts, self.time_base))
+        });
+        frame.saturating_sub(first) + 1
+    }
+
+    pub fn len(&self) -> u64 {
+        self.starts.len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.starts.is_empty()
+    }
+
+    /// Whether frame durations spread by more than 10%; dropped frames count.
+    pub fn is_variable(&self) -> bool {
+        let mut durations = self.durations();
+        let Some(first) = durations.next() else {
+            return false;
+        };
+        let (min, max) = durations.fold((first, first), |(lo, hi), d| (lo.min(d), hi.max(d)));
+        (max - min) * 10 > min
+    }
+
+    /// Mean frame rate over the whole stream.
+    pub fn average_rate(&self) -> Option<Rational> {
+        let span = self.end.checked_sub(*self.starts.first()?)?;
+        if span == 0 {
+            return None;
+        }
+        Rational::from_fps(self.len() as f64 * tps() as f64 / span as f64)
+    }
+
+    /// Frame shown at `ticks`: the last one starting at or before it.
+    pub fn frame_at(&self, ticks: u64) -> u64 {
+        (self.starts.partition_point(|&s| s <= ticks) as u64).saturating_sub(1)
+    }
+
+    /// A time inside the display interval of `frame` (its middle), so that
+    /// rounding on the way to a stream timestamp cannot land on a neighbour.
+    /// Frames past the end clamp to the last one.
+    pub fn ticks_of(&self, frame: u64) -> u64 {
+        let Some(last) = self.starts.len().checked_sub(1) else {
+            return 0;
+        };
+        let i = (frame as usize).min(last);
+        let next = self.starts.get(i + 1).copied().unwrap_or(self.end);
+        self.starts[i] + next.saturating_sub(self.starts[i]) / 2
+    }
+
+    fn durations(&self) -> impl Iterator<Item = u64> + '_ {
+        self.starts
+            .iter()
+            .zip(self.starts.iter().skip(1).chain([&self.end]))
+            .map(|(a, b)| b.saturating_sub(*a))
+            .filter(|&d| d > 0)
+    }
+
+    /// Reads the presentation times of every video packet in `path`.
+    pub fn build(path: &Path) -> MediaResult<Self> {
+        #[cfg(feature = "real_ffmpeg")]
+        {
+            let name = path.display().to_string();
+            build_ffmpeg(path).map_err(|e| MediaError::from_ffmpeg(&name, e))
+        }
+        #[cfg(not(feature = "real_ffmpeg"))]
+        {
+            // Synthetic sources are constant rate by construction.
+            let name = path.to_string_lossy();
+            let source = crate::pattern::SyntheticSource::from_path(&name).map_err(|e| {
+                MediaError::Unsupported {
+                    path: name.to_string(),
+                    reason: e.to_string(),
+                }
+            })?;
+            // One intra-coded packet per frame, timestamped in frames.
+            let rate = source.frame_rate;
+            let end = source.duration_secs * tps();
+            let packets = (0..time::ticks_to_frame(end, rate))
+                .map(|n| Packet {
+                    pts: n as i64,
+                    pos: -1,
+                    key: true,
+                })
+                .collect();
+            Ok(Self::new(
+                Rational::new(rate.den, rate.num),
+                0,
+                packets,
+                end,
+            ))
+        }
+    }
+}
+
+#[cfg(feature = "real_ffmpeg")]
+fn build_ffmpeg(path: &Path) -> std::result::Result<FrameIndex, ffmpeg_next::Error> {
+    use ffmpeg_next::{ffi, format, media};
+
+    let mut input = format::input(&path)?;
+    let stream = input
+        .streams()
+        .best(media::Type::Video)
+        .ok_or(ffmpeg_next::Error::StreamNotFound)?;
+    let stream_index = stream.index();
+    let time_base = Rational::new(
+        i64::from(stream.time_base().numerator()),
+        i64::from(stream.time_base().denominator()),
+    );
+    let start = match stream.start_time() {
+        ffi::AV_NOPTS_VALUE => 0,
+        t => t,
+    };
+
+    let mut packets = Vec::new();
+    let mut last_end = i64::MIN;
+    for (stream, packet) in input.packets() {
+        if stream.index() != stream_index {
+            continue;
+        }
+        if let Some(pts) = packet.pts().or(packet.dts()) {
+            packets.push(Packet {
+                pts,
+                pos: packet.position() as i64,
+                key: packet.is_key(),
+            });
+            last_end = last_end.max(pts + packet.duration().max(1));
+        }
+    }
+    if packets.is_empty() {
+        return Err(ffmpeg_next::Error::InvalidData);
+    }
+    let end = time::pts_to_ticks(last_end - start, time_base);
+    Ok(FrameIndex::new(time_base, start, packets, end))
+}
+
+const MAGIC: &[u8; 8] = b"LNIDX002";
+
+/// `<dir>/<stem>-<hash>.idx`; the hash tells apart sources with the same name.
+pub fn cache_file(dir: &Path, source: &str) -> PathBuf {
+    let stem = Path::new(source)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "source".to_string());
+    let hash = fnv1a(source.as_bytes());
+    dir.join(format!("{stem}-{hash:016x}.idx"))
+}
+
+/// 64-bit FNV-1a. Unlike `DefaultHasher` it never changes between builds,
+/// which would orphan every cached index.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Size and modification time of `source`, stored with the index so a
+/// replaced file is indexed again.
+fn stamp(source: &Path) -> Option<[u64; 2]> {
+    let meta = std::fs::metadata(source).ok()?;
+    let modified = meta
+        .modified()
+        .ok()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()?;
+    Some([meta.len(), modified.as_secs()])
+}
+
+impl FrameIndex {
+    /// Reads a cached index for `source`, if one exists and still matches the file.
+    pub fn load(file: &Path, source: &Path) -> Option<Self> {
+        let mut bytes = Vec::new();
+        std::fs::File::open(file)
+            .ok()?
+            .read_to_end(&mut bytes)
+            .ok()?;
+        let rest = bytes.strip_prefix(MAGIC)?;
+        let mut words = rest
+            .chunks_exact(8)
+            .map(|c| i64::from_le_bytes(c.try_into().unwrap()));
+        let [size, modified] = stamp(source)?;
+        if words.next()? as u64 != size || words.next()? as u64 != modified {
+            return None;
+        }
+        let time_base = Rational::new(words.next()?, words.next()?);
+        let start_pts = words.next()?;
+        let end = words.next()? as u64;
+        let count = words.next()? as usize;
+        let mut packets = Vec::with_capacity(count.min(rest.len() / 24));
+        for _ in 0..count {
+            packets.push(Packet {
+                pts: words.next()?,
+                pos: words.next()?,
+                key: words.next()? != 0,
+            });
+        }
+        time_base
+            .is_valid()
+            .then(|| Self::new(time_base, start_pts, packets, end))
+    }
+
+    /// Writes the index for `source` to `file`, replacing it atomically.
+    /// Sources that are not plain files (synthetic ones) are not cached.
+    pub fn save(&self, file: &Path, source: &Path) -> std::io::Result<()> {
+        let Some([size, modified]) = stamp(source) else {
+            return Ok(());
+        };
+        let header = [
+            size as i64,
+            modified as i64,
+            self.time_base.num,
+            self.time_base.den,
+            self.start_pts,
+            self.end as i64,
+            self.packets.len() as i64,
+        ];
+        let packets = self
+            .packets
+            .iter()
+            .flat_map(|p| [p.pts, p.pos, i64::from(p.key)]);
+        let mut bytes =
+            Vec::with_capacity(MAGIC.len() + (header.len() + self.packets.len() * 3) * 8);
+        bytes.extend_from_slice(MAGIC);
+        for word in header.into_iter().chain(packets) {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        if let Some(dir) = file.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp = file.with_extension("idx.tmp");
+        std::fs::File::create(&tmp)?.write_all(&bytes)?;
+        std::fs::rename(tmp, file)
+    }
+}
+
+/// Source frame to show and the source time to decode it at, for a request
+/// at `ticks` of source time.
+///
+/// With `conform`, frames are taken as evenly spaced at that rate, whatever
+/// their timestamps say. Otherwise the index decides, falling back to the
+/// nominal rate while it is not built yet.
+pub fn resolve(
+    index: Option<&FrameIndex>,
+    conform: Option<Rational>,
+    nominal: Rational,
+    ticks: u64,
+) -> (u64, u64) {
+    match (index.filter(|i| !i.is_empty()), conform) {
+        (Some(index), Some(rate)) => {
+            let frame = time::ticks_to_frame(ticks, rate).min(index.len() - 1);
+            (frame, index.ticks_of(frame))
+        }
+        (None, Some(rate)) => {
+            let frame = time::ticks_to_frame(ticks, rate);
+            (frame, time::frame_to_ticks(frame, nominal))
+        }
+        (Some(index), None) if index.is_variable() => {
+            let frame = index.frame_at(ticks);
+            (frame, index.ticks_of(frame))
+        }
+        _ => (time::ticks_to_frame(ticks, nominal), ticks),
+    }
+}
+
+/// Finished indexes by source path, shared with the render path.
+#[derive(Default, Clone)]
+pub struct IndexRegistry {
+    ready: Arc<Mutex<HashMap<String, Arc<FrameIndex>>>>,
+}
+
+impl IndexRegistry {
+    pub fn get(&self, source: &str) -> Option<Arc<FrameIndex>> {
+        self.ready.lock().unwrap().get(source).cloned()
+    }
+
+    pub fn insert(&self, source: String, index: FrameIndex) {
+        self.ready.lock().unwrap().insert(source, Arc::new(index));
+    }
+
+    pub fn clear(&self) {
+        self.ready.lock().unwrap().clear();
+    }
+}
+
+/// Index outcome for a source path, sent back from a background job.
+pub type IndexResult = (String, MediaResult<FrameIndex>);
+
+/// Loads the index of `source` from `cache_dir`, or builds it and stores it there.
+pub fn load_or_build(source: &str, cache_dir: Option<&Path>) -> MediaResult<FrameIndex> {
+    let path = Path::new(source);
+    let file = cache_dir.map(|dir| cache_file(dir, source));
+    if let Some(index) = file.as_deref().and_then(|f| FrameIndex::load(f, path)) {
+        return Ok(index);
+    }
+    let index = FrameIndex::build(path)?;
+    if let Some(file) = file
+        && let Err(e) = index.save(&file, path)
+    {
+        eprintln!("Warning: could not cache index of {source}: {e}");
+    }
+    Ok(index)
+}
+
+/// Builds the job body that indexes `source` and reports back on `done`.
+pub fn index_job(
+    source: String,
+    cache_dir: Option<PathBuf>,
+    done: mpsc::Sender<IndexResult>,
+) -> impl FnOnce() + Send + 'static {
+    move || {
+        let index = load_or_build(&source, cache_dir.as_deref());
+        let _ = done.send((source, index));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Rational = Rational::new(1, 1000);
+
+    fn ms(pts: i64) -> u64 {
+        time::pts_to_ticks(pts, MS)
+    }
+
+    fn packet(pts: i64, key: bool) -> Packet {
+        Packet {
+            pts,
+            pos: pts * 10,
+            key,
+        }
+    }
+
+    /// Frames shown at 100, 120, 140, 160, 200 and 240 ms, the last two
+    /// after dropped frames, in decode order with B-frames. Keyframes at 100
+    /// and 160.
+    fn variable() -> FrameIndex {
+        let packets = vec![
+            packet(100, true),
+            packet(140, false),
+            packet(120, false),
+            packet(160, true),
+            packet(240, false),
+            packet(200, false),
+        ];
+        FrameIndex::new(MS, 100, packets, ms(180))
+    }
+
+    fn constant() -> FrameIndex {
+        let packets = (0..10).map(|n| packet(n * 20, n % 5 == 0)).collect();
+        FrameIndex::new(MS, 0, packets, ms(200))
+    }
+
+    #[test]
+    fn frames_are_found_in_display_order() {
+        let index = variable();
+        assert_eq!(index.len(), 6);
+        assert_eq!(index.frame_at(0), 0);
+        assert_eq!(index.frame_at(ms(19)), 0);
+        assert_eq!(index.frame_at(ms(20)), 1);
+        assert_eq!(index.frame_at(ms(99)), 3);
+        assert_eq!(index.frame_at(ms(110)), 4);
+        assert_eq!(index.frame_at(ms(10_000)), 5);
+    }
+
+    #[test]
+    fn ticks_of_lands_inside_the_frame() {
+        let index = variable();
+        for frame in 0..index.len() {
+            assert_eq!(
+                index.frame_at(index.ticks_of(frame)),
+                frame,
+                "frame {frame}"
+            );
+        }
+        assert_eq!(index.ticks_of(0), ms(20) / 2);
+        // The last frame lasts until the end of the stream.
+        assert_eq!(index.ticks_of(5), ms(140) + (ms(180) - ms(140)) / 2);
+        assert_eq!(index.ticks_of(99), index.ticks_of(5));
+    }
+
+    #[test]
+    fn keyframes_are_found_by_pts() {
+        let index = variable();
+        assert_eq!(index.keyframe_before(99), None);
+        assert_eq!(index.keyframe_before(100), Some(packet(100, true)));
+        assert_eq!(index.keyframe_before(150), Some(packet(100, true)));
+        assert_eq!(index.keyframe_before(160), Some(packet(160, true)));
+        assert_eq!(index.keyframe_before(1000), Some(packet(160, true)));
+
+        assert_eq!(index.decode_cost(index.ticks_of(0)), 1);
+        assert_eq!(index.decode_cost(index.ticks_of(2)), 3);
+        assert_eq!(index.decode_cost(index.ticks_of(3)), 1);
+        assert_eq!(index.decode_cost(index.ticks_of(5)), 3);
+    }
+
+    #[test]
+    fn dropped_frames_make_a_stream_variable() {
+        assert!(variable().is_variable());
+        assert!(!constant().is_variable());
+        assert_eq!(constant().average_rate(), Some(Rational::new(50, 1)));
+    }
+
+    #[test]
+    fn cache_file_names_are_stable() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
+
+        let dir = Path::new("cache");
+        let file = cache_file(dir, "/media/a/clip.mov");
+        assert_eq!(file, cache_file(dir, "/media/a/clip.mov"));
+        assert_ne!(file, cache_file(dir, "/media/b/clip.mov"));
+        assert!(
+            file.file_name()
+                .unwrap()
+                .to_string_lossy()
+                .starts_with("clip-")
+        );
+    }
+
+    #[test]
+    fn saved_index_loads_until_the_source_changes() {
+        let dir = std::env::temp_dir().join(format!("lunaris-index-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("clip.mov");
+        std::fs::write(&source, b"not really a movie").unwrap();
+        let file = cache_file(&dir.join("cache"), &source.to_string_lossy());
+
+        let index = variable();
+        index.save(&file, &source).unwrap();
+        let bytes = std::fs::read(&file).unwrap();
+        assert!(bytes.starts_with(b"LNIDX002"));
+        assert_eq!(FrameIndex::load(&file, &source), Some(index));
+
+        // A replaced file is indexed again.
+        std::fs::write(&source, b"a different movie").unwrap();
+        assert_eq!(FrameIndex::load(&file, &source), None);
+
+        // Sources that are not files are never cached.
+        let synthetic = dir.join("synthetic.idx");
+        constant()
+            .save(&synthetic, Path::new("synthetic:bars"))
+            .unwrap();
+        assert!(!synthetic.exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
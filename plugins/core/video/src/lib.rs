@@ -1,24 +1,109 @@
+use futures::{channel::oneshot, future::BoxFuture};
 use lunaris_api::{
     export_plugin,
     plugin::{Plugin, PluginContext, PluginReport, Renderer, RenderJob, RenderTask},
+    request::{Orchestrator, Priority},
     util::error::{Result, LunarisError},
 };
 use lunaris_ecs::prelude::*;
+use timeline::components::{
+    BindTo, MediaState, Playhead, SourceOffset, TimelineElement, TimelineSpan,
+};
 use std::{
     collections::HashMap,
-    path::PathBuf,
-    sync::{Arc, Mutex},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock, mpsc},
 };
 
+mod audio;
+mod cache;
+mod color;
 mod components;
 mod decoder;
-use decoder::Decoder;
+mod error;
+mod frame;
+mod geometry;
+mod image_source;
+mod index;
+mod offline;
+mod params;
+mod pattern;
+mod pool;
+mod prefetch;
+mod probe;
+mod proxy;
+mod time;
+pub use audio::{AudioBuffer, AudioJob};
+pub use color::{ColorInfo, HdrMetadata, Matrix, Primaries, Range, Transfer, WorkingSpace};
+pub use error::{MediaError, MediaResult};
+pub use frame::{Downscale, FrameFormat, FrameSpec, VideoFrame};
+pub use geometry::FieldOrder;
+use audio::{AudioDecoder, AudioDecoders};
+pub use components::{
+    CacheCompression, CheckMedia, ColorSettings, ConformRate, FieldOrderOverride,
+    FrameCacheSettings, MediaCacheDir, MediaInfo, PixelAspectOverride, PrefetchSettings,
+    ProxyMedia, ProxyStatus, RelinkMedia, RelinkStatus, RotationOverride, VariableFrameRate,
+    VideoSource,
+};
+pub use pattern::{Pattern, SyntheticSource, frame_checksum, read_frame_number, timecode};
+pub use probe::probe;
+pub use time::Rational;
+use cache::{FrameCache, FrameKey};
+use components::DecoderSettings;
+use geometry::{FrameView, Geometry, Overrides};
+use index::{FrameIndex, IndexRegistry, IndexResult};
+use pool::{DecoderPool, SourceInfo};
+use offline::RelinkResult;
+use prefetch::Prefetcher;
+use proxy::{ProxyRegistry, ProxyResult};
 
 export_plugin!(VideoPlugin, id: "lunaris.core.video", name: "Video Backend", [Renderer]);
 
 pub struct VideoPlugin {
-    // Cache decoders by path to avoid re-opening files
-    decoders: Arc<Mutex<HashMap<String, Arc<Mutex<Decoder>>>>>,
+    // Open decoders by path, several per file, to avoid re-opening files
+    decoders: Arc<DecoderPool>,
+    settings: DecoderSettings,
+    // Decoded frames, checked before anything is scheduled.
+    cache: Arc<FrameCache>,
+    cache_settings: FrameCacheSettings,
+    // Playhead tracking and queued read-ahead jobs.
+    prefetch: Prefetcher,
+    prefetch_settings: PrefetchSettings,
+    color_settings: ColorSettings,
+    // Audio decoders by path, opened by the first job that needs one and
+    // capped like `decoders`; audio requests are sequential per clip.
+    audio: AudioDecoders,
+    // Finished proxies, consulted for preview-quality jobs.
+    proxies: ProxyRegistry,
+    // Whether `proxies` has been filled from the `ProxyMedia` already in the
+    // world, which a loaded project brings along as `Ready`.
+    proxies_synced: bool,
+    proxy_tx: mpsc::Sender<ProxyResult>,
+    proxy_rx: Mutex<mpsc::Receiver<ProxyResult>>,
+    // Path last checked for existence, per source entity.
+    checked: HashMap<Entity, String>,
+    online_tx: mpsc::Sender<OnlineResult>,
+    online_rx: Mutex<mpsc::Receiver<OnlineResult>>,
+    relink_tx: mpsc::Sender<RelinkResult>,
+    relink_rx: Mutex<mpsc::Receiver<RelinkResult>>,
+    // Path last submitted for probing, per source entity.
+    probed: HashMap<Entity, String>,
+    probe_tx: mpsc::Sender<ProbeResult>,
+    probe_rx: Mutex<mpsc::Receiver<ProbeResult>>,
+    // Decode failures by requested path, turned into `MediaState` on the sources.
+    failure_tx: mpsc::Sender<(String, MediaError)>,
+    failure_rx: Mutex<mpsc::Receiver<(String, MediaError)>>,
+
+    indexes: IndexRegistry,
+    index_tx: mpsc::Sender<IndexResult>,
+    index_rx: Mutex<mpsc::Receiver<IndexResult>>,
+    cache_dir: Option<PathBuf>,
+    // Probed rate, size and geometry and per-source overrides by path, refreshed
+    // every update so render jobs can resolve without the world or the file.
+    source_info: HashMap<String, (Option<SourceInfo>, Option<Geometry>, Overrides)>,
+
+    // Decode runs as blocking orchestrator jobs; set once in `init`.
+    orch: OnceLock<Orchestrator>,
 }
 
 impl Plugin for VideoPlugin {
@@ -37,13 +122,62 @@ impl Plugin for VideoPlugin {
         {
             eprintln!("Warning: VideoPlugin running in MOCK mode (no ffmpeg)");
         }
-        
+
+        let (proxy_tx, proxy_rx) = mpsc::channel();
+        let (online_tx, online_rx) = mpsc::channel();
+        let (relink_tx, relink_rx) = mpsc::channel();
+        let (probe_tx, probe_rx) = mpsc::channel();
+        let (failure_tx, failure_rx) = mpsc::channel();
+        let (index_tx, index_rx) = mpsc::channel();
         Self {
-            decoders: Arc::new(Mutex::new(HashMap::new())),
+            decoders: Arc::new(DecoderPool::new(DecoderSettings::default())),
+            settings: DecoderSettings::default(),
+            cache: Arc::new(FrameCache::new(FrameCacheSettings::default())),
+            cache_settings: FrameCacheSettings::default(),
+            prefetch: Prefetcher::default(),
+            prefetch_settings: PrefetchSettings::default(),
+            color_settings: ColorSettings::default(),
+            audio: AudioDecoders::new(DecoderSettings::default().max_open_decoders),
+            proxies: ProxyRegistry::default(),
+            proxies_synced: false,
+            proxy_tx,
+            proxy_rx: Mutex::new(proxy_rx),
+            checked: HashMap::new(),
+            online_tx,
+            online_rx: Mutex::new(online_rx),
+            relink_tx,
+            relink_rx: Mutex::new(relink_rx),
+            probed: HashMap::new(),
+            probe_tx,
+            probe_rx: Mutex::new(probe_rx),
+            failure_tx,
+            failure_rx: Mutex::new(failure_rx),
+            indexes: IndexRegistry::default(),
+            index_tx,
+            index_rx: Mutex::new(index_rx),
+            cache_dir: None,
+            source_info: HashMap::new(),
+            orch: OnceLock::new(),
         }
     }
 
-    fn init(&self, _ctx: PluginContext<'_>) -> Result {
+    fn init(&self, ctx: PluginContext<'_>) -> Result {
+        let _ = self.orch.set(ctx.orch.clone());
+        ctx.world.insert_resource(self.settings);
+        ctx.world.insert_resource(self.cache_settings);
+        ctx.world.insert_resource(self.prefetch_settings);
+        ctx.world.insert_resource(self.color_settings);
+        // Clip edits snapshot these, so undo brings back a clip's media too.
+        // A source respawned by undoing a delete is a new entity, so it is
+        // probed and indexed again like any added source, which restores
+        // `MediaInfo`, `MediaState` and `VariableFrameRate`.
+        timeline::history::track_sized::<VideoSource>(ctx.world, |s| {
+            std::mem::size_of::<VideoSource>() + s.path.capacity()
+        });
+        timeline::history::track::<FieldOrderOverride>(ctx.world);
+        timeline::history::track::<RotationOverride>(ctx.world);
+        timeline::history::track::<PixelAspectOverride>(ctx.world);
+        timeline::history::track::<ConformRate>(ctx.world);
         Ok(())
     }
 
@@ -51,7 +185,35 @@ impl Plugin for VideoPlugin {
         Ok(())
     }
 
-    fn update_world(&mut self, _ctx: PluginContext<'_>) -> Result {
+    fn update_world(&mut self, ctx: PluginContext<'_>) -> Result {
+        if let Some(settings) = ctx.world.get_resource::<DecoderSettings>()
+            && *settings != self.settings
+        {
+            self.settings = *settings;
+            self.decoders.set_settings(self.settings);
+            self.audio.set_limit(self.settings.max_open_decoders);
+        }
+        if let Some(settings) = ctx.world.get_resource::<FrameCacheSettings>()
+            && *settings != self.cache_settings
+        {
+            self.cache_settings = *settings;
+            self.cache.set_settings(self.cache_settings);
+        }
+        if let Some(settings) = ctx.world.get_resource::<PrefetchSettings>() {
+            self.prefetch_settings = *settings;
+        }
+        self.cache_dir = ctx.world.get_resource::<MediaCacheDir>().map(|d| d.0.clone());
+        // Cached frames are keyed by working space, so a change needs no flush.
+        if let Some(settings) = ctx.world.get_resource::<ColorSettings>() {
+            self.color_settings = *settings;
+        }
+        self.update_offline(ctx.world, &ctx.orch);
+        self.update_probes(ctx.world, &ctx.orch);
+        self.update_indexes(ctx.world);
+        self.update_failures(ctx.world);
+        self.update_geometry(ctx.world);
+        self.update_proxies(ctx.world, &ctx.orch);
+        self.update_prefetch(ctx.world, &ctx.orch);
         Ok(())
     }
 
@@ -62,55 +224,788 @@ impl Plugin for VideoPlugin {
     fn shutdown(&mut self, _ctx: PluginContext<'_>) {}
 
     fn reset(&mut self, _ctx: PluginContext<'_>) {
-        self.decoders.lock().unwrap().clear();
+        self.decoders.clear();
+        self.cache.clear();
+        self.prefetch.reset();
+        self.audio.clear();
+        self.proxies.clear();
+        self.proxies_synced = false;
+        self.checked.clear();
+        self.probed.clear();
+        self.source_info.clear();
+        self.indexes.clear();
     }
 }
 
+/// Probe outcome for a source entity, sent back from a background job.
+type ProbeResult = (Entity, String, MediaResult<MediaInfo>);
+
+/// Whether a source entity's file exists, sent back from a background job.
+type OnlineResult = (Entity, String, bool);
+
+/// Maps the job's timeline position into the clip's source time.
+///
+/// The clip is described by `clip_start`/`clip_end` (its `TimelineElement.position`)
+/// plus `in_point` and `speed` (its `SourceOffset`). Jobs without them are
+/// treated as already clip-local.
+fn source_tick(job: &RenderJob, timeline_tick: u64) -> Result<u64> {
+    let (span, offset) = clip_mapping(job)?;
+    map_to_source(&span, &offset, timeline_tick)
+}
+
+/// The clip placement a job describes with `clip_start`, `clip_end`,
+/// `in_point` and `speed`.
+fn clip_mapping(job: &RenderJob) -> Result<(TimelineSpan, SourceOffset)> {
+    let span = TimelineSpan {
+        start: params::uint(job, "clip_start")?.unwrap_or(0),
+        end: params::uint(job, "clip_end")?.unwrap_or(u64::MAX),
+    };
+    let defaults = SourceOffset::default();
+    let offset = SourceOffset {
+        in_point: params::uint(job, "in_point")?.unwrap_or(defaults.in_point),
+        speed: params::float(job, "speed")?.unwrap_or(defaults.speed),
+    };
+    Ok((span, offset))
+}
+
+fn map_to_source(span: &TimelineSpan, offset: &SourceOffset, timeline_tick: u64) -> Result<u64> {
+    offset
+        .source_tick(span, timeline_tick)
+        .ok_or(LunarisError::InvalidArgument {
+            name: "frame".to_string(),
+            reason: Some("Frame lies outside the clip".to_string()),
+        })
+}
+
 impl Renderer for VideoPlugin {
     fn schedule_render(&self, job: RenderJob) -> Result<RenderTask> {
-        let path_prop = job.parameter("path").ok_or(LunarisError::InvalidArgument {
-            name: "path".to_string(),
-            reason: Some("Missing 'path' property for video render job".to_string()),
-        })?;
-
-        let path_str = match path_prop {
-            lunaris_api::types::Property::String(s) => s.clone(),
-            lunaris_api::types::Property::Path(p) => p.to_string_lossy().to_string(),
-            _ => return Err(LunarisError::InvalidArgument {
-                name: "path".to_string(),
-                reason: Some("Property 'path' must be String or Path".to_string()),
-            }),
+        if params::string(&job, "kind")? == Some("audio") {
+            return Err(LunarisError::InvalidArgument {
+                name: "kind".to_string(),
+                reason: Some("Audio jobs go through VideoPlugin::schedule_audio".to_string()),
+            });
+        }
+        // Compositing consumes RGBA, so this is where native frames get converted.
+        // HDR and linear working spaces need more than 8 bits to survive.
+        let format = match self.color_settings.working.transfer {
+            Transfer::Linear => FrameFormat::RgbaF32,
+            Transfer::Pq | Transfer::Hlg => FrameFormat::Rgba16,
+            Transfer::Bt709 | Transfer::Srgb => FrameFormat::Rgba8,
+        };
+        let frame = self.schedule_native(&job, format)?;
+        Ok(Box::pin(async move { frame.await?.into_raw_image() }))
+    }
+}
+
+/// What a video job asks for, read from its parameters and the plugin's state
+/// without touching the file, see `VideoPlugin::plan_job`.
+struct JobPlan {
+    /// The `VideoSource` path the job names.
+    source: String,
+    /// File to decode: the source or its proxy.
+    path: String,
+    /// Rate, size and geometry of `path`, if known without opening it.
+    info: Option<SourceInfo>,
+    /// Probed geometry of the original, which a proxy keeps.
+    geometry: Option<Geometry>,
+    overrides: Overrides,
+    /// Packet index of the original source, if built.
+    index: Option<Arc<FrameIndex>>,
+    /// `index` when `path` is the source it was built for.
+    seek_index: Option<Arc<FrameIndex>>,
+    sequence_rate: Option<Rational>,
+    frame_rate: Option<Rational>,
+    conform_rate: Option<Rational>,
+    frame: u64,
+    clip: (TimelineSpan, SourceOffset),
+    downscale: Downscale,
+    format: FrameFormat,
+    working: WorkingSpace,
+}
+
+/// A video job resolved against its source's rate and size, see [`JobPlan::resolve`].
+struct ResolvedJob {
+    key: FrameKey,
+    /// Source time to decode at.
+    ticks: u64,
+}
+
+impl JobPlan {
+    fn resolve(&self, info: SourceInfo) -> Result<ResolvedJob> {
+        // job.frame counts timeline frames at the job's rate, or the stream's
+        // own rate when none is given. Converting through ticks keeps 23.976
+        // and friends exact instead of drifting like integer milliseconds would.
+        // Image sequences carry no timing; `sequence_rate` sets it (default 24).
+        // Variable rate sources go through their frame index once it is built,
+        // unless the clip conforms them to `conform_rate`.
+        let (stream_rate, source_size, stream_geometry) = info;
+        let source_rate = self.sequence_rate.unwrap_or(stream_rate);
+        let frame_rate = self.frame_rate.unwrap_or(source_rate);
+        let timeline_tick = time::frame_to_ticks(self.frame, frame_rate);
+        let (span, offset) = &self.clip;
+        let (source_frame, ticks) = index::resolve(
+            self.index.as_deref(),
+            self.conform_rate,
+            source_rate,
+            map_to_source(span, offset, timeline_tick)?,
+        );
+
+        // Interlacing, rotation and non-square pixels are corrected before
+        // downscaling, so limits apply to the picture as displayed.
+        let geometry = self
+            .geometry
+            .unwrap_or(stream_geometry)
+            .with_overrides(&self.overrides);
+        let view = FrameView {
+            working: self.working,
+            ..geometry.view(&self.overrides)
         };
+        let (display_w, display_h) = geometry.display_size(source_size.0, source_size.1);
+        let (width, height) = self.downscale.apply(display_w, display_h);
+        let spec = FrameSpec {
+            format: self.format,
+            width,
+            height,
+        };
+
+        Ok(ResolvedJob {
+            key: FrameKey {
+                source: self.path.clone(),
+                frame: source_frame,
+                spec,
+                view,
+            },
+            ticks,
+        })
+    }
+}
+
+/// Expected work for a frame request, see [`VideoPlugin::estimate_frame`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameCost {
+    pub cached: bool,
+    /// Frames decoded from the preceding keyframe; `None` until the source is indexed.
+    pub decode_frames: Option<u64>,
+}
+
+/// Future resolving to a decoded frame in its requested layout. Bad job
+/// parameters are rejected when scheduling; the task itself fails with the
+/// [`MediaError`] that says what is wrong with the media.
+pub type FrameTask = BoxFuture<'static, MediaResult<VideoFrame>>;
+
+/// Future resolving to the PCM of an audio job, failing like [`FrameTask`].
+pub type AudioTask = BoxFuture<'static, MediaResult<AudioBuffer>>;
+
+/// Audio is requested ahead of the playhead and buffered, so it queues below
+/// the frame on screen but above background work like probing and proxies.
+const AUDIO_PRIORITY: Priority = Priority::Normal;
+
+impl VideoPlugin {
+    /// Like `Renderer::schedule_render`, but keeps the frame in the layout named by
+    /// the job's `pixel_format` (`rgba`, `nv12`, `yuv420p`, `p010`, `rgba16` or
+    /// `rgbaf32`; default `rgba`) so consumers that upload planar YUV skip the RGBA
+    /// conversion entirely. Frames are in the [`ColorSettings`] working space and
+    /// carry their [`ColorInfo`].
+    ///
+    /// Both entry points honour `scale`, `max_width` and `max_height` to decode
+    /// straight to a smaller resolution, e.g. quarter size while scrubbing, and
+    /// `quality = "preview"` to read from the source's proxy when one is ready.
+    ///
+    /// `path` may also name a still (PNG, JPEG, QOI, EXR) or an image sequence
+    /// such as `shot_%04d.exr`, played at `sequence_rate` (default 24 fps).
+    ///
+    /// `conform_rate` plays the source's frames back to back at that rate,
+    /// ignoring their timestamps; see [`ConformRate`].
+    pub fn schedule_frame(&self, job: RenderJob) -> Result<FrameTask> {
+        self.schedule_native(&job, params::pixel_format(&job)?)
+    }
+
+    /// What a frame request costs: whether it is served from the cache and,
+    /// once the source's packet index is built, how many frames have to be
+    /// decoded from the keyframe before it. Lets a scheduler order or drop
+    /// requests before committing to them.
+    ///
+    /// A source that has been neither probed nor opened reports an uncached
+    /// frame of unknown cost rather than opening the file here.
+    pub fn estimate_frame(&self, job: &RenderJob) -> Result<FrameCost> {
+        let plan = self.plan_job(job, params::pixel_format(job)?)?;
+        let Some(info) = plan.info else {
+            return Ok(FrameCost {
+                cached: false,
+                decode_frames: None,
+            });
+        };
+        let resolved = plan.resolve(info)?;
+        Ok(FrameCost {
+            cached: self.cache.contains(&resolved.key),
+            decode_frames: plan
+                .seek_index
+                .map(|index| index.decode_cost(resolved.ticks)),
+        })
+    }
+
+    fn schedule_native(&self, job: &RenderJob, format: FrameFormat) -> Result<FrameTask> {
+        let plan = self.plan_job(job, format)?;
+        let resolved = plan.info.map(|info| plan.resolve(info)).transpose()?;
 
-        // Get or create decoder
-        let decoder = {
-            let mut cache = self.decoders.lock().unwrap();
-            if let Some(d) = cache.get(&path_str) {
-                d.clone()
+        // Scrubbing over recently shown frames never reaches the decoder.
+        if let Some(resolved) = &resolved
+            && let Some(frame) = self.cache.get(&resolved.key)
+        {
+            return Ok(Box::pin(async move { Ok(frame) }));
+        }
+
+        let decoders = self.decoders.clone();
+        let cache = self.cache.clone();
+        let failures = self.failure_tx.clone();
+        let path = plan.path.clone();
+        let decode = move || {
+            // Failures mark the source, also when its proxy was being read.
+            let fail = |e: MediaError| -> MediaError {
+                let _ = failures.send((plan.source.clone(), e.clone()));
+                e
+            };
+            let ResolvedJob { key, ticks } = match resolved {
+                Some(resolved) => resolved,
+                // Neither probed nor opened yet: find out here, off the
+                // scheduling thread, then check the cache like above.
+                None => {
+                    let info = decoders.source_info(&plan.path).map_err(fail)?;
+                    let resolved = plan.resolve(info).map_err(|e| MediaError::Other {
+                        path: plan.path.clone(),
+                        reason: e.to_string(),
+                    })?;
+                    if let Some(frame) = cache.get(&resolved.key) {
+                        return Ok(frame);
+                    }
+                    resolved
+                }
+            };
+            let decoded = decoders
+                .acquire(&plan.path, Some(ticks))
+                .and_then(|mut decoder| {
+                    if let Some(rate) = plan.sequence_rate {
+                        decoder.set_image_rate(rate);
+                    }
+                    if let Some(index) = plan.seek_index.clone() {
+                        decoder.set_index(index);
+                    }
+                    decoder.decode_frame(ticks, key.spec, key.view)
+                });
+            match decoded {
+                Ok(frame) => {
+                    cache.insert(key, frame.clone());
+                    Ok(frame)
+                }
+                Err(e) => Err(fail(e)),
+            }
+        };
+        Ok(self.spawn_decode(path, decode, Priority::VideoFrame))
+    }
+
+    /// Reads which file, frame, size limits and view a video job asks for.
+    /// Never opens the file: rate and size come from a decoder opened earlier
+    /// or from the source's `MediaInfo`, and are left unknown otherwise.
+    fn plan_job(&self, job: &RenderJob, format: FrameFormat) -> Result<JobPlan> {
+        let source = params::path(job)?;
+        let mut path = source.clone();
+        // Overrides belong to the original, whichever file is decoded.
+        let (probed, geometry, overrides) =
+            self.source_info.get(&path).copied().unwrap_or_default();
+        let index = self.indexes.get(&path);
+        let mut seek_index = index.clone();
+        let mut info = self.decoders.cached_info(&path).or(probed);
+        // Preview jobs read the proxy once it exists; timestamps match the original.
+        if params::string(job, "quality")? == Some("preview")
+            && let Some(proxy) = self.proxies.get(&path)
+        {
+            path = proxy;
+            seek_index = None;
+            info = self.decoders.cached_info(&path);
+        }
+        let sequence_rate = params::rational(job, "sequence_rate")?
+            .filter(|_| image_source::is_image_path(Path::new(&path)));
+
+        Ok(JobPlan {
+            source,
+            path,
+            info,
+            geometry,
+            overrides,
+            index,
+            seek_index,
+            sequence_rate,
+            frame_rate: params::rational(job, "frame_rate")?,
+            conform_rate: params::rational(job, "conform_rate")?,
+            frame: job.frame,
+            clip: clip_mapping(job)?,
+            downscale: Downscale {
+                scale: params::float(job, "scale")?,
+                max_width: params::uint(job, "max_width")?.map(|w| w as u32),
+                max_height: params::uint(job, "max_height")?.map(|h| h as u32),
+            },
+            format,
+            working: self.color_settings.working,
+        })
+    }
+
+    /// Audio counterpart of `Renderer::schedule_render` for jobs with `kind = "audio"`.
+    ///
+    /// Takes `path`, `start_tick`/`end_tick` and the same clip mapping parameters as
+    /// video jobs, plus optional `sample_rate` (default 48 kHz) and `channels` (default 2).
+    /// Resolves to interleaved `f32` covering exactly the requested range.
+    pub fn schedule_audio(&self, job: RenderJob) -> Result<AudioTask> {
+        let job = AudioJob::from_render_job(&job)?;
+        let decoder = self.audio.slot(&job.path);
+        let path = job.path.clone();
+        let decode = move || {
+            let mut slot = decoder.lock().unwrap();
+            // A failed open leaves the slot empty so the next job tries again.
+            // Failures are not reported on the source: a video without sound
+            // is still online.
+            if slot.is_none() {
+                *slot = Some(AudioDecoder::new(Path::new(&job.path))?);
+            }
+            let decoder = slot.as_mut().expect("audio decoder opened above");
+            decoder.decode_range(&job)
+        };
+        Ok(self.spawn_decode(path, decode, AUDIO_PRIORITY))
+    }
+
+    /// Probes sources that were added (or repointed) since the last update in the
+    /// background, and attaches `MediaInfo` to them once the probe finishes.
+    /// Streams are indexed by the same job right after, see [`index`].
+    fn update_probes(&mut self, world: &mut World, orch: &Orchestrator) {
+        self.probed
+            .retain(|&entity, _| world.get::<VideoSource>(entity).is_some());
+        let mut q = world.query::<(Entity, &VideoSource, Option<&MediaState>)>();
+        for (entity, source, state) in q.iter(world) {
+            // Offline sources are probed again once they come back.
+            if self.probed.get(&entity) == Some(&source.path)
+                || matches!(state, Some(MediaState::Offline { .. }))
+            {
+                continue;
+            }
+            self.probed.insert(entity, source.path.clone());
+            let path = source.path.clone();
+            let done = self.probe_tx.clone();
+            let index_job = (!image_source::is_image_path(Path::new(&path))).then(|| {
+                index::index_job(path.clone(), self.cache_dir.clone(), self.index_tx.clone())
+            });
+            let _ = orch.submit_job_boxed(
+                Box::new(move || {
+                    let info = probe::probe(Path::new(&path));
+                    let probed = info.is_ok();
+                    let _ = done.send((entity, path, info));
+                    if probed && let Some(index_job) = index_job {
+                        index_job();
+                    }
+                }),
+                Priority::Background,
+            );
+        }
+
+        let finished: Vec<ProbeResult> = self.probe_rx.lock().unwrap().try_iter().collect();
+        for (entity, path, info) in finished {
+            // Skip results for sources that were removed or repointed meanwhile.
+            let current = world.get::<VideoSource>(entity).map(|s| s.path.clone());
+            if current.as_deref() != Some(path.as_str()) {
+                continue;
+            }
+            match info {
+                Ok(info) => {
+                    world.entity_mut(entity).insert((info, MediaState::Online));
+                }
+                Err(e) => {
+                    eprintln!("Warning: {}", e);
+                    if let Some(state) = e.media_state() {
+                        world.entity_mut(entity).insert(state);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Stores finished frame indexes and flags the sources that turned out to be
+    /// variable rate. Their cached frames were numbered by the nominal rate, so
+    /// they are dropped.
+    fn update_indexes(&mut self, world: &mut World) {
+        let finished: Vec<IndexResult> = self.index_rx.lock().unwrap().try_iter().collect();
+        for (path, index) in finished {
+            let index = match index {
+                Ok(index) => index,
+                Err(e) => {
+                    eprintln!("Warning: could not index {}", e);
+                    continue;
+                }
+            };
+            let variable = index.is_variable().then(|| VariableFrameRate {
+                frames: index.len(),
+                average: index.average_rate(),
+            });
+            if variable.is_some() {
+                self.cache.remove_source(&path);
+                if let Some(proxy) = self.proxies.get(&path) {
+                    self.cache.remove_source(&proxy);
+                }
+            }
+            let mut q = world.query::<(Entity, &VideoSource)>();
+            let entities: Vec<Entity> = q
+                .iter(world)
+                .filter(|(_, s)| s.path == path)
+                .map(|(e, _)| e)
+                .collect();
+            for entity in entities {
+                match variable {
+                    Some(vfr) => {
+                        world.entity_mut(entity).insert(vfr);
+                    }
+                    None => {
+                        world.entity_mut(entity).remove::<VariableFrameRate>();
+                    }
+                }
+            }
+            self.indexes.insert(path, index);
+        }
+    }
+
+    /// Marks sources whose files are missing as offline and brings back the ones
+    /// that reappeared. Checks new or repointed sources, and all of them when
+    /// `CheckMedia` is present, in the background. Also drives `RelinkMedia`.
+    fn update_offline(&mut self, world: &mut World, orch: &Orchestrator) {
+        // Forget sources that were despawned or stopped being sources.
+        self.checked
+            .retain(|&entity, _| world.get::<VideoSource>(entity).is_some());
+        let recheck_all = world.remove_resource::<CheckMedia>().is_some();
+        let mut q = world.query::<(Entity, &VideoSource)>();
+        let mut unchecked: Vec<(Entity, String)> = Vec::new();
+        for (entity, source) in q.iter(world) {
+            if !recheck_all && self.checked.get(&entity) == Some(&source.path) {
+                continue;
+            }
+            self.checked.insert(entity, source.path.clone());
+            unchecked.push((entity, source.path.clone()));
+        }
+        if !unchecked.is_empty() {
+            // Network shares and sleeping disks can take long to answer.
+            let done = self.online_tx.clone();
+            let _ = orch.submit_job_boxed(
+                Box::new(move || {
+                    for (entity, path) in unchecked {
+                        let online = offline::is_online(&path);
+                        let _ = done.send((entity, path, online));
+                    }
+                }),
+                Priority::Background,
+            );
+        }
+
+        let finished: Vec<OnlineResult> = self.online_rx.lock().unwrap().try_iter().collect();
+        let mut changes: Vec<(Entity, bool)> = Vec::new();
+        for (entity, path, online) in finished {
+            // Skip results for sources that were removed or repointed meanwhile.
+            let current = world.get::<VideoSource>(entity).map(|s| s.path.as_str());
+            if current != Some(path.as_str()) {
+                continue;
+            }
+            let was_offline = matches!(
+                world.get::<MediaState>(entity),
+                Some(MediaState::Offline { .. })
+            );
+            if online == was_offline {
+                changes.push((entity, online));
+            }
+        }
+        for (entity, online) in changes {
+            if online {
+                // Probing again restores `MediaState::Online` and fresh metadata.
+                world.entity_mut(entity).remove::<MediaState>();
+                self.probed.remove(&entity);
             } else {
-                let d = Arc::new(Mutex::new(Decoder::new(&PathBuf::from(&path_str))?));
-                cache.insert(path_str.clone(), d.clone());
-                d
+                world.entity_mut(entity).insert(MediaState::Offline {
+                    reason: "File not found".to_string(),
+                });
             }
+        }
+
+        if let Some(mut relink) = world.get_resource_mut::<RelinkMedia>()
+            && relink.status == RelinkStatus::Requested
+        {
+            relink.status = RelinkStatus::Searching;
+            let directory = relink.directory.clone();
+            let mut q = world.query::<(&VideoSource, &MediaState, Option<&MediaInfo>)>();
+            let mut missing: Vec<offline::Missing> = Vec::new();
+            for (source, state, info) in q.iter(world) {
+                if matches!(state, MediaState::Offline { .. })
+                    && !missing.iter().any(|m| m.path == source.path)
+                {
+                    missing.push(offline::Missing {
+                        path: source.path.clone(),
+                        info: info.cloned(),
+                    });
+                }
+            }
+            let _ = orch.submit_job_boxed(
+                Box::new(offline::relink_job(directory, missing, self.relink_tx.clone())),
+                Priority::Background,
+            );
+        }
+
+        let finished: Vec<RelinkResult> = self.relink_rx.lock().unwrap().try_iter().collect();
+        for result in finished {
+            let status = match result {
+                Ok(moves) => {
+                    let mut relinked = 0;
+                    let mut q = world.query::<&mut VideoSource>();
+                    for mut source in q.iter_mut(world) {
+                        let found = moves.iter().find(|(old, _)| *old == source.path);
+                        if let Some((_, new_path)) = found {
+                            source.path = new_path.clone();
+                            relinked += 1;
+                        }
+                    }
+                    RelinkStatus::Done { relinked }
+                }
+                Err(e) => RelinkStatus::Failed(e),
+            };
+            if let Some(mut relink) = world.get_resource_mut::<RelinkMedia>() {
+                relink.status = status;
+            }
+        }
+    }
+
+    /// Marks sources whose decodes failed as offline or unsupported.
+    fn update_failures(&mut self, world: &mut World) {
+        let failed: Vec<(String, MediaError)> =
+            self.failure_rx.lock().unwrap().try_iter().collect();
+        if failed.is_empty() {
+            return;
+        }
+        let mut q = world.query::<(Entity, &VideoSource)>();
+        let sources: Vec<(Entity, String)> = q
+            .iter(world)
+            .map(|(entity, source)| (entity, source.path.clone()))
+            .collect();
+        for (path, e) in failed {
+            let Some(state) = e.media_state() else {
+                continue;
+            };
+            eprintln!("Warning: {}", e);
+            for (entity, _) in sources.iter().filter(|(_, p)| *p == path) {
+                world.entity_mut(*entity).insert(state.clone());
+            }
+        }
+    }
+
+    /// Collects the probed rate, size and geometry and the override components
+    /// of every source by path.
+    fn update_geometry(&mut self, world: &mut World) {
+        let mut q = world.query::<(
+            &VideoSource,
+            Option<&MediaInfo>,
+            Option<&FieldOrderOverride>,
+            Option<&RotationOverride>,
+            Option<&PixelAspectOverride>,
+        )>();
+        self.source_info = q
+            .iter(world)
+            .map(|(source, info, field_order, rotation, pixel_aspect)| {
+                let overrides = Overrides {
+                    field_order: field_order.map(|o| o.0),
+                    rotation: rotation.map(|o| o.0),
+                    pixel_aspect: pixel_aspect.map(|o| o.0),
+                };
+                let probed = info.and_then(|info| {
+                    let rate = info.frame_rate?;
+                    Some((rate, (info.width, info.height), info.geometry()))
+                });
+                let geometry = info.map(MediaInfo::geometry);
+                (source.path.clone(), (probed, geometry, overrides))
+            })
+            .collect();
+    }
+
+    /// Queues background decodes of the frames the playhead is about to reach
+    /// on every clip under it, in the direction and at the speed it is moving.
+    fn update_prefetch(&mut self, world: &mut World, orch: &Orchestrator) {
+        let Some(position) = world.query::<&Playhead>().iter(world).next().map(|p| p.current)
+        else {
+            return;
         };
+        let Some(motion) = self.prefetch.observe(position) else {
+            return;
+        };
+        let settings = self.prefetch_settings;
+        if settings.frames == 0 {
+            return;
+        }
 
-        // Calculate timestamp (assuming 60fps for now, should be in job params)
-        // job.frame is the global timeline frame. We need local time.
-        // Assuming job.frame IS the local time for this clip for now.
-        // In a real DAG, the time would be mapped.
-        let timestamp_ms = (job.frame * 1000 / 60) as i64;
-
-        // Return a future that executes the decode on a thread pool (or here if blocking)
-        // Since RenderTask is BoxFuture, we can use async block.
-        // However, ffmpeg is blocking. We should ideally spawn_blocking.
-        // But we don't have access to tokio runtime here directly unless we use Handle::current().
-        
-        Ok(Box::pin(async move {
-            // This will block the executor thread if not careful. 
-            // For high-perf, this should be offloaded.
-            // Assuming the caller (Orchestrator) runs this on a worker thread.
-            let mut dec = decoder.lock().unwrap();
-            dec.decode_frame(timestamp_ms)
-        }))
+        // Clips carry their source directly or bind to a source entity.
+        let mut q = world.query::<(
+            Entity,
+            &TimelineElement,
+            Option<&SourceOffset>,
+            Option<&BindTo>,
+            Option<&ConformRate>,
+        )>();
+        let clips: Vec<(Entity, TimelineSpan, SourceOffset, Option<Rational>)> = q
+            .iter(world)
+            .map(|(entity, el, offset, bind, conform)| {
+                (
+                    bind.map_or(entity, |b| b.id),
+                    el.position,
+                    offset.copied().unwrap_or_default(),
+                    conform.map(|c| c.0),
+                )
+            })
+            .collect();
+        for (entity, span, offset, conform) in clips {
+            let (Some(source), Some(info)) =
+                (world.get::<VideoSource>(entity), world.get::<MediaInfo>(entity))
+            else {
+                continue;
+            };
+            // Not probed yet; the key would not match what renders ask for.
+            let Some(rate) = info.frame_rate else {
+                continue;
+            };
+            let (_, _, overrides) = self
+                .source_info
+                .get(&source.path)
+                .copied()
+                .unwrap_or_default();
+            let index = self.indexes.get(&source.path);
+            let geometry = info.geometry().with_overrides(&overrides);
+            let view = FrameView {
+                working: self.color_settings.working,
+                ..geometry.view(&overrides)
+            };
+            let (display_w, display_h) = geometry.display_size(info.width, info.height);
+            let (width, height) = settings.downscale.apply(display_w, display_h);
+            let spec = FrameSpec {
+                format: settings.format,
+                width,
+                height,
+            };
+            let step = time::frame_to_ticks(motion.stride(), rate);
+            for k in 1..=settings.frames as u64 {
+                let Some(at) = position.checked_add_signed(motion.direction() * (k * step) as i64)
+                else {
+                    break;
+                };
+                let Some(ticks) = offset.source_tick(&span, at) else {
+                    continue;
+                };
+                let (frame, ticks) = index::resolve(index.as_deref(), conform, rate, ticks);
+                let key = FrameKey {
+                    source: source.path.clone(),
+                    frame,
+                    spec,
+                    view,
+                };
+                if self.cache.contains(&key) {
+                    continue;
+                }
+                let Some(ticket) = self.prefetch.claim(key) else {
+                    continue;
+                };
+                let decoders = self.decoders.clone();
+                let cache = self.cache.clone();
+                let path = source.path.clone();
+                let index = index.clone();
+                let _ = orch.submit_job_boxed(
+                    Box::new(move || {
+                        prefetch::read_ahead(ticket, &decoders, &cache, &path, ticks, index);
+                    }),
+                    Priority::Background,
+                );
+            }
+        }
+    }
+
+    /// Starts requested proxy transcodes and records finished ones on their entities.
+    /// After a reset, proxies the world already marks `Ready` are registered again.
+    fn update_proxies(&mut self, world: &mut World, orch: &Orchestrator) {
+        let mut q = world.query::<(&VideoSource, &mut ProxyMedia)>();
+        if !self.proxies_synced {
+            self.proxies_synced = true;
+            for (source, mut proxy) in q.iter_mut(world) {
+                if proxy.status != ProxyStatus::Ready {
+                    continue;
+                }
+                if Path::new(&proxy.path).is_file() {
+                    self.proxies.insert(source.path.clone(), proxy.path.clone());
+                } else {
+                    // Deleted since it was made; transcode it again below.
+                    proxy.status = ProxyStatus::Requested;
+                }
+            }
+        }
+        for (source, mut proxy) in q.iter_mut(world) {
+            if proxy.status != ProxyStatus::Requested {
+                continue;
+            }
+            proxy.status = ProxyStatus::Generating;
+            let _ = orch.submit_job_boxed(
+                Box::new(proxy::transcode_job(
+                    source.path.clone(),
+                    proxy.path.clone(),
+                    self.proxy_tx.clone(),
+                )),
+                Priority::Background,
+            );
+        }
+
+        let finished: Vec<ProxyResult> = self.proxy_rx.lock().unwrap().try_iter().collect();
+        if finished.is_empty() {
+            return;
+        }
+        for (source, mut proxy) in q.iter_mut(world) {
+            let Some(result) = finished
+                .iter()
+                .find(|r| r.source == source.path && r.proxy == proxy.path)
+            else {
+                continue;
+            };
+            proxy.status = result.status.clone();
+        }
+        for result in finished {
+            if result.status == ProxyStatus::Ready {
+                self.proxies.insert(result.source, result.proxy);
+            }
+        }
+    }
+
+    /// Runs blocking FFmpeg work as an orchestrator job and hands back a future
+    /// for its result, so executor threads never wait on decode.
+    fn spawn_decode<T, F>(
+        &self,
+        path: String,
+        decode: F,
+        priority: Priority,
+    ) -> BoxFuture<'static, MediaResult<T>>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> MediaResult<T> + Send + 'static,
+    {
+        let Some(orch) = self.orch.get() else {
+            // Not initialised (e.g. driven directly in tests); decode when polled.
+            return Box::pin(async move { decode() });
+        };
+        let (tx, rx) = oneshot::channel();
+        let _ = orch.submit_job_boxed(
+            Box::new(move || {
+                let _ = tx.send(decode());
+            }),
+            priority,
+        );
+        Box::pin(async move {
+            rx.await.unwrap_or_else(|_| {
+                Err(MediaError::Other {
+                    path,
+                    reason: "Decode job was dropped before it finished".to_string(),
+                })
+            })
+        })
     }
 }
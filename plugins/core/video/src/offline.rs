@@ -0,0 +1,111 @@
+//! Missing-file detection and relinking.
+//!
+//! A source is online when its file exists (for image sequences, when at least
+//! one frame does). Relinking walks a directory tree for files with the same
+//! name as a missing source and accepts a candidate only if its duration,
+//! resolution and file size agree with what was probed before the file went
+//! missing.
+
+use crate::{components::MediaInfo, image_source, probe};
+use lunaris_api::consts::tps;
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc,
+};
+
+/// Whether the media behind `path` can currently be reached.
+pub fn is_online(path: &str) -> bool {
+    if path.starts_with("synthetic:") {
+        return true;
+    }
+    let path = Path::new(path);
+    if image_source::is_image_path(path) && path.to_string_lossy().contains('%') {
+        return image_source::ImageSource::open(path).is_ok();
+    }
+    path.exists()
+}
+
+/// A source to find, with the metadata probed while it was still online.
+pub struct Missing {
+    pub path: String,
+    pub info: Option<MediaInfo>,
+}
+
+/// New paths for the missing sources that were found, or why the search failed.
+pub type RelinkResult = Result<Vec<(String, String)>, String>;
+
+/// Builds the job body that searches `directory` for `missing` and reports on `done`.
+pub fn relink_job(
+    directory: PathBuf,
+    missing: Vec<Missing>,
+    done: mpsc::Sender<RelinkResult>,
+) -> impl FnOnce() + Send + 'static {
+    move || {
+        let _ = done.send(relink(&directory, &missing));
+    }
+}
+
+fn relink(directory: &Path, missing: &[Missing]) -> RelinkResult {
+    if !directory.is_dir() {
+        return Err(format!("{} is not a directory", directory.display()));
+    }
+    let mut dirs = Vec::new();
+    collect_dirs(directory, &mut dirs);
+
+    let mut found = Vec::new();
+    for source in missing {
+        let Some(name) = Path::new(&source.path).file_name() else {
+            continue;
+        };
+        let candidates: Vec<PathBuf> = dirs
+            .iter()
+            .map(|dir| dir.join(name))
+            .filter(|c| is_online(&c.to_string_lossy()))
+            .collect();
+        let chosen = match &source.info {
+            Some(info) => candidates.into_iter().find(|c| matches(info, c)),
+            // Nothing to compare against; only an unambiguous name will do.
+            None if candidates.len() == 1 => candidates.into_iter().next(),
+            None => None,
+        };
+        if let Some(new_path) = chosen {
+            found.push((source.path.clone(), new_path.to_string_lossy().to_string()));
+        }
+    }
+    Ok(found)
+}
+
+/// Every directory under `root`, including it. Symlinked directories are not
+/// followed, so links back up the tree cannot loop.
+fn collect_dirs(root: &Path, out: &mut Vec<PathBuf>) {
+    out.push(root.to_path_buf());
+    let Ok(entries) = std::fs::read_dir(root) else {
+        return;
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let hidden = entry.file_name().to_string_lossy().starts_with('.');
+        if !hidden && entry.file_type().is_ok_and(|t| t.is_dir()) {
+            collect_dirs(&entry.path(), out);
+        }
+    }
+}
+
+/// Whether `candidate` looks like the file `expected` was probed from.
+fn matches(expected: &MediaInfo, candidate: &Path) -> bool {
+    let Ok(found) = probe::probe(candidate) else {
+        return false;
+    };
+    if expected.file_size != 0 && found.file_size != 0 && expected.file_size != found.file_size {
+        return false;
+    }
+    if (expected.width, expected.height) != (found.width, found.height) {
+        return false;
+    }
+    // Containers round durations differently; allow a frame, or half a second
+    // when the rate is unknown.
+    let tolerance = match expected.frame_rate {
+        Some(rate) => crate::time::frame_to_ticks(1, rate),
+        None => tps() / 2,
+    };
+    expected.duration.abs_diff(found.duration) <= tolerance
+}
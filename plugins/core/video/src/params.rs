@@ -0,0 +1,91 @@
+//! Typed accessors for optional `RenderJob` parameters.
+
+use crate::{frame::FrameFormat, time::Rational};
+use lunaris_api::{
+    plugin::RenderJob,
+    types::Property,
+    util::error::{LunarisError, Result},
+};
+
+fn invalid(name: &str, reason: &str) -> LunarisError {
+    LunarisError::InvalidArgument {
+        name: name.to_string(),
+        reason: Some(reason.to_string()),
+    }
+}
+
+/// Reads the required `path` property naming the source file.
+pub fn path(job: &RenderJob) -> Result<String> {
+    match job.parameter("path") {
+        Some(Property::String(s)) => Ok(s.clone()),
+        Some(Property::Path(p)) => Ok(p.to_string_lossy().to_string()),
+        Some(_) => Err(invalid("path", "Property 'path' must be String or Path")),
+        None => Err(invalid("path", "Missing 'path' property for render job")),
+    }
+}
+
+/// Reads an optional string property such as `kind`.
+pub fn string<'a>(job: &'a RenderJob, name: &str) -> Result<Option<&'a str>> {
+    match job.parameter(name) {
+        Some(Property::String(s)) => Ok(Some(s.as_str())),
+        Some(_) => Err(invalid(name, "Expected a string")),
+        None => Ok(None),
+    }
+}
+
+/// Reads a rate such as `frame_rate`, given as `"24000/1001"`, `"25"` or a float.
+pub fn rational(job: &RenderJob, name: &str) -> Result<Option<Rational>> {
+    let Some(prop) = job.parameter(name) else {
+        return Ok(None);
+    };
+    let parsed = match prop {
+        Property::String(s) => Rational::parse(s),
+        Property::Float(f) => Rational::from_fps(*f),
+        Property::Integer(i) => Some(Rational::new(*i, 1)).filter(Rational::is_valid),
+        _ => None,
+    };
+    parsed
+        .map(Some)
+        .ok_or_else(|| invalid(name, "Expected a positive rate like \"24000/1001\" or 25"))
+}
+
+/// Reads a non-negative integer such as a tick position.
+pub fn uint(job: &RenderJob, name: &str) -> Result<Option<u64>> {
+    let Some(prop) = job.parameter(name) else {
+        return Ok(None);
+    };
+    let parsed = match prop {
+        Property::Integer(i) => u64::try_from(*i).ok(),
+        Property::String(s) => s.trim().parse().ok(),
+        _ => None,
+    };
+    parsed
+        .map(Some)
+        .ok_or_else(|| invalid(name, "Expected a non-negative integer"))
+}
+
+/// Reads a finite float such as a playback speed.
+pub fn float(job: &RenderJob, name: &str) -> Result<Option<f64>> {
+    let Some(prop) = job.parameter(name) else {
+        return Ok(None);
+    };
+    let parsed = match prop {
+        Property::Float(f) => Some(*f),
+        Property::Integer(i) => Some(*i as f64),
+        Property::String(s) => s.trim().parse().ok(),
+        _ => None,
+    };
+    parsed
+        .filter(|f| f.is_finite())
+        .map(Some)
+        .ok_or_else(|| invalid(name, "Expected a number"))
+}
+
+/// Reads the `pixel_format` of a frame job; RGBA when absent.
+pub fn pixel_format(job: &RenderJob) -> Result<FrameFormat> {
+    match string(job, "pixel_format")? {
+        Some(s) => FrameFormat::parse(s)
+            .ok_or_else(|| invalid("pixel_format", &format!("Unknown pixel format '{s}'"))),
+        None => Ok(FrameFormat::default()),
+    }
+}
@@ -0,0 +1,365 @@
+//! Deterministic synthetic sources, used by the mock decoder so frame accuracy
+//! and scaling can be checked without FFmpeg.
+//!
+//! A source is selected through its path:
+//! `synthetic:<pattern>?size=1280x720&rate=24000/1001&duration=60&overlay=1`.
+//! Every parameter is optional. Paths that are not `synthetic:` URLs get the
+//! default SMPTE bars at 1920x1080, 60 fps, with overlay.
+//!
+//! Each frame carries its frame number as a 24-bit barcode along the bottom
+//! edge (see [`read_frame_number`]); it survives downscaling and YUV round trips.
+
+use crate::time::Rational;
+use lunaris_api::util::error::{LunarisError, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Pattern {
+    /// 75% SMPTE colour bars.
+    #[default]
+    Bars,
+    /// Gradients that scroll with the frame number.
+    Gradient,
+    /// Per-pixel hash of (frame, x, y); any two frames differ everywhere,
+    /// so a whole-frame checksum pins down the frame.
+    Checksum,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntheticSource {
+    pub pattern: Pattern,
+    pub width: u32,
+    pub height: u32,
+    pub frame_rate: Rational,
+    /// Length in seconds, reported by the mock probe.
+    pub duration_secs: u64,
+    /// Burn in frame number and timecode.
+    pub overlay: bool,
+}
+
+impl Default for SyntheticSource {
+    fn default() -> Self {
+        Self {
+            pattern: Pattern::Bars,
+            width: 1920,
+            height: 1080,
+            frame_rate: Rational::new(60, 1),
+            duration_secs: 60,
+            overlay: true,
+        }
+    }
+}
+
+/// Height of the frame-number barcode as a fraction of the frame height.
+const BARCODE_DIV: u32 = 16;
+const BARCODE_BITS: u32 = 24;
+
+impl SyntheticSource {
+    pub fn from_path(path: &str) -> Result<Self> {
+        let Some(spec) = path.strip_prefix("synthetic:") else {
+            return Ok(Self::default());
+        };
+        let bad = |what: &str| LunarisError::InvalidArgument {
+            name: "path".to_string(),
+            reason: Some(format!("Bad synthetic source {what} in '{path}'")),
+        };
+        let (pattern, query) = spec.split_once('?').unwrap_or((spec, ""));
+        let mut src = Self {
+            pattern: match pattern {
+                "" | "bars" => Pattern::Bars,
+                "gradient" => Pattern::Gradient,
+                "checksum" => Pattern::Checksum,
+                _ => return Err(bad("pattern")),
+            },
+            ..Self::default()
+        };
+        for pair in query.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = pair.split_once('=').ok_or_else(|| bad("parameter"))?;
+            match key {
+                "size" => {
+                    let (w, h) = value.split_once('x').ok_or_else(|| bad("size"))?;
+                    src.width = w.parse().map_err(|_| bad("size"))?;
+                    src.height = h.parse().map_err(|_| bad("size"))?;
+                    if src.width < 2 || src.height < 2 {
+                        return Err(bad("size"));
+                    }
+                }
+                "rate" => src.frame_rate = Rational::parse(value).ok_or_else(|| bad("rate"))?,
+                "duration" => src.duration_secs = value.parse().map_err(|_| bad("duration"))?,
+                "overlay" => src.overlay = matches!(value, "1" | "true" | "on"),
+                _ => return Err(bad("parameter")),
+            }
+        }
+        Ok(src)
+    }
+
+    /// Renders frame `frame` as tightly packed RGBA at `width`x`height`.
+    pub fn render(&self, frame: u64, width: u32, height: u32) -> Vec<u8> {
+        let (w, h) = (width as usize, height as usize);
+        let mut data = vec![0u8; w * h * 4];
+        for y in 0..h {
+            for x in 0..w {
+                let px = match self.pattern {
+                    Pattern::Bars => bars(x, y, w, h),
+                    Pattern::Gradient => gradient(frame, x, y, w, h),
+                    Pattern::Checksum => checksum_pixel(frame, x as u32, y as u32),
+                };
+                data[(y * w + x) * 4..][..4].copy_from_slice(&px);
+            }
+        }
+        draw_barcode(&mut data, w, h, frame);
+        if self.overlay {
+            let text = format!("{} {}", frame, timecode(frame, self.frame_rate));
+            draw_text(&mut data, w, h, &text);
+        }
+        data
+    }
+}
+
+/// Reads the frame number back from the barcode of an RGBA frame.
+pub fn read_frame_number(rgba: &[u8], width: u32, height: u32) -> Option<u64> {
+    let (w, h) = (width as usize, height as usize);
+    if rgba.len() < w * h * 4 || h < BARCODE_DIV as usize {
+        return None;
+    }
+    let y = h - (h / BARCODE_DIV as usize) / 2 - 1;
+    let mut value = 0u64;
+    for bit in 0..BARCODE_BITS as usize {
+        let x = (bit * 2 + 1) * w / (BARCODE_BITS as usize * 2);
+        let luma = rgba[(y * w + x) * 4..][..3]
+            .iter()
+            .map(|&c| u32::from(c))
+            .sum::<u32>()
+            / 3;
+        value = (value << 1) | u64::from(luma >= 128);
+    }
+    Some(value)
+}
+
+/// FNV-1a over the frame bytes, for comparing frames in tests.
+pub fn frame_checksum(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |h, &b| {
+        (h ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// `HH:MM:SS:FF` for frame `frame` at `rate`, counting whole frames per second.
+pub fn timecode(frame: u64, rate: Rational) -> String {
+    let fps = (rate.num as u64).div_ceil(rate.den as u64).max(1);
+    let (secs, ff) = (frame / fps, frame % fps);
+    format!(
+        "{:02}:{:02}:{:02}:{:02}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        ff
+    )
+}
+
+fn bars(x: usize, y: usize, w: usize, h: usize) -> [u8; 4] {
+    const HI: u8 = 191;
+    const TOP: [[u8; 3]; 7] = [
+        [HI, HI, HI],
+        [HI, HI, 0],
+        [0, HI, HI],
+        [0, HI, 0],
+        [HI, 0, HI],
+        [HI, 0, 0],
+        [0, 0, HI],
+    ];
+    const MID: [[u8; 3]; 7] = [
+        [0, 0, HI],
+        [19, 19, 19],
+        [HI, 0, HI],
+        [19, 19, 19],
+        [0, HI, HI],
+        [19, 19, 19],
+        [HI, HI, HI],
+    ];
+    let col = (x * 7 / w).min(6);
+    let [r, g, b] = if y < h * 2 / 3 {
+        TOP[col]
+    } else if y < h * 3 / 4 {
+        MID[col]
+    } else {
+        // PLUGE-style bottom row: -I, white, +Q, then black.
+        match x * 6 / w {
+            0 => [0, 33, 76],
+            1 => [255, 255, 255],
+            2 => [50, 0, 106],
+            _ => [19, 19, 19],
+        }
+    };
+    [r, g, b, 255]
+}
+
+fn gradient(frame: u64, x: usize, y: usize, w: usize, h: usize) -> [u8; 4] {
+    let shift = (frame * 4) as usize;
+    let r = ((x + shift) * 255 / w.max(1)) % 256;
+    let g = y * 255 / h.max(1);
+    let b = (frame * 3 % 256) as usize;
+    [r as u8, g as u8, b as u8, 255]
+}
+
+fn checksum_pixel(frame: u64, x: u32, y: u32) -> [u8; 4] {
+    // SplitMix64 of the packed coordinates.
+    let mut z = frame
+        .wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        .wrapping_add((u64::from(y) << 32) | u64::from(x));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    let [r, g, b, ..] = z.to_le_bytes();
+    [r, g, b, 255]
+}
+
+fn draw_barcode(data: &mut [u8], w: usize, h: usize, frame: u64) {
+    let bar_h = (h / BARCODE_DIV as usize).max(1);
+    for y in h - bar_h..h {
+        for x in 0..w {
+            let bit = x * BARCODE_BITS as usize / w;
+            let on = (frame >> (BARCODE_BITS as usize - 1 - bit)) & 1 == 1;
+            let v = if on { 255 } else { 0 };
+            data[(y * w + x) * 4..][..4].copy_from_slice(&[v, v, v, 255]);
+        }
+    }
+}
+
+/// 3x5 glyphs for digits and ':'; bit 2 of each row is the leftmost column.
+const GLYPHS: [[u8; 5]; 11] = [
+    [7, 5, 5, 5, 7],
+    [2, 6, 2, 2, 7],
+    [7, 1, 7, 4, 7],
+    [7, 1, 7, 1, 7],
+    [5, 5, 7, 1, 1],
+    [7, 4, 7, 1, 7],
+    [7, 4, 7, 5, 7],
+    [7, 1, 1, 1, 1],
+    [7, 5, 7, 5, 7],
+    [7, 5, 7, 1, 7],
+    [0, 2, 0, 2, 0],
+];
+
+/// Draws `text` white on black in the top-left corner, sized to the frame.
+fn draw_text(data: &mut [u8], w: usize, h: usize, text: &str) {
+    let px = (h / 60).max(1);
+    let (x0, y0) = (px * 2, px * 2);
+    let box_w = (text.len() * 4 + 1) * px;
+    let box_h = 7 * px;
+    for y in y0..(y0 + box_h).min(h) {
+        for x in x0..(x0 + box_w).min(w) {
+            data[(y * w + x) * 4..][..4].copy_from_slice(&[0, 0, 0, 255]);
+        }
+    }
+    for (i, c) in text.chars().enumerate() {
+        let glyph = match c {
+            '0'..='9' => GLYPHS[c as usize - '0' as usize],
+            ':' => GLYPHS[10],
+            _ => continue,
+        };
+        for (row, bits) in glyph.iter().enumerate() {
+            for col in 0..3 {
+                if bits & (4 >> col) == 0 {
+                    continue;
+                }
+                let gx = x0 + (1 + i * 4 + col) * px;
+                let gy = y0 + (1 + row) * px;
+                for y in gy..(gy + px).min(h) {
+                    for x in gx..(gx + px).min(w) {
+                        data[(y * w + x) * 4..][..4].copy_from_slice(&[255, 255, 255, 255]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// With FFmpeg the decoder opens real files instead of rendering these.
+#[cfg(all(test, not(feature = "real_ffmpeg")))]
+mod tests {
+    use super::*;
+    use crate::{
+        decoder::Decoder,
+        frame::{FrameFormat, FrameSpec, VideoFrame},
+        geometry::FrameView,
+        time,
+    };
+    use std::path::Path;
+
+    fn decode(path: &str, frame: u64, width: u32, height: u32) -> VideoFrame {
+        let mut decoder = Decoder::new(Path::new(path)).unwrap();
+        let ticks = time::frame_to_ticks(frame, decoder.frame_rate());
+        let spec = FrameSpec {
+            format: FrameFormat::Rgba8,
+            width,
+            height,
+        };
+        decoder
+            .decode_frame(ticks, spec, FrameView::default())
+            .unwrap()
+    }
+
+    fn pixel(frame: &VideoFrame, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * frame.width + x) * 4) as usize;
+        frame.planes[0][i..i + 4].try_into().unwrap()
+    }
+
+    const CHECKSUM_PATH: &str = "synthetic:checksum?size=64x36&rate=24&overlay=0";
+
+    #[test]
+    fn checksum_frames_decode_to_known_checksums() {
+        let expected = [
+            (0, 0x8502_8cef_064b_97e2),
+            (1, 0x2155_3432_1ef7_4cfc),
+            (47, 0xc6fc_8a82_98ce_b010),
+            (1000, 0xb2c6_ea03_0796_ee04),
+        ];
+        for (n, checksum) in expected {
+            let frame = decode(CHECKSUM_PATH, n, 64, 36);
+            assert_eq!(frame_checksum(&frame.planes[0]), checksum, "frame {n}");
+            assert_eq!(read_frame_number(&frame.planes[0], 64, 36), Some(n));
+        }
+    }
+
+    #[test]
+    fn checksum_frames_decode_to_known_pixels() {
+        let expected = [
+            (0, (5, 7), [234, 122, 201, 255]),
+            (0, (63, 0), [21, 128, 64, 255]),
+            (1, (5, 7), [48, 169, 2, 255]),
+            (47, (5, 7), [3, 131, 16, 255]),
+            (47, (63, 0), [98, 176, 160, 255]),
+            (1000, (5, 7), [83, 0, 130, 255]),
+        ];
+        for (n, (x, y), rgba) in expected {
+            let frame = decode(CHECKSUM_PATH, n, 64, 36);
+            assert_eq!(pixel(&frame, x, y), rgba, "frame {n} at {x},{y}");
+        }
+    }
+
+    #[test]
+    fn bars_decode_to_their_colours() {
+        let frame = decode("synthetic:bars?size=140x80&overlay=0", 5, 140, 80);
+        assert_eq!(frame_checksum(&frame.planes[0]), 0x56bc_f302_a00d_8e7d);
+        // Top row: white, yellow, ..., blue.
+        assert_eq!(pixel(&frame, 10, 10), [191, 191, 191, 255]);
+        assert_eq!(pixel(&frame, 30, 10), [191, 191, 0, 255]);
+        assert_eq!(pixel(&frame, 130, 10), [0, 0, 191, 255]);
+        // Middle row starts with blue, bottom row with -I, white, then black.
+        assert_eq!(pixel(&frame, 10, 55), [0, 0, 191, 255]);
+        assert_eq!(pixel(&frame, 10, 65), [0, 33, 76, 255]);
+        assert_eq!(pixel(&frame, 30, 65), [255, 255, 255, 255]);
+        assert_eq!(pixel(&frame, 130, 65), [19, 19, 19, 255]);
+        assert_eq!(read_frame_number(&frame.planes[0], 140, 80), Some(5));
+    }
+
+    #[test]
+    fn overlay_burns_in_the_frame_number() {
+        let frame = decode("synthetic:bars?size=320x180&rate=25", 30, 320, 180);
+        assert_eq!(frame_checksum(&frame.planes[0]), 0x959e_5060_1009_2efd);
+        // The text box starts 6 px in; its first glyph, '3', starts 3 px further.
+        assert_eq!(pixel(&frame, 4, 4), [191, 191, 191, 255]);
+        assert_eq!(pixel(&frame, 7, 7), [0, 0, 0, 255]);
+        assert_eq!(pixel(&frame, 9, 9), [255, 255, 255, 255]);
+        assert_eq!(read_frame_number(&frame.planes[0], 320, 180), Some(30));
+    }
+}
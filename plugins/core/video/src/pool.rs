@@ -0,0 +1,356 @@
+//! Bounded pool of decoders per source file.
+//!
+//! Each source may have several open decoders so frames of one long recording
+//! can be decoded in parallel. The total number of open handles is capped;
+//! when the cap is hit, idle decoders of the least recently used source are closed.
+
+use crate::{
+    components::DecoderSettings, decoder::Decoder, error::MediaResult, geometry::Geometry,
+    time::Rational,
+};
+use std::{
+    collections::HashMap,
+    ops::{Deref, DerefMut},
+    path::Path,
+    sync::{Arc, Condvar, Mutex},
+};
+
+pub struct DecoderPool {
+    state: Mutex<PoolState>,
+    // Signalled whenever a decoder is returned or a handle is closed.
+    returned: Condvar,
+}
+
+#[derive(Default)]
+struct PoolState {
+    sources: HashMap<String, SourceEntry>,
+    settings: DecoderSettings,
+    open: usize,
+    clock: u64,
+}
+
+#[derive(Default)]
+struct SourceEntry {
+    idle: Vec<Decoder>,
+    busy: usize,
+    last_used: u64,
+    /// Frame rate, coded size and geometry, known once a decoder has been opened.
+    info: Option<SourceInfo>,
+}
+
+/// Frame rate, coded size and stored geometry of a source.
+pub type SourceInfo = (Rational, (u32, u32), Geometry);
+
+/// A decoder checked out of the pool. Returned to its source's idle list on drop.
+pub struct DecoderLease {
+    pool: Arc<DecoderPool>,
+    path: String,
+    decoder: Option<Decoder>,
+}
+
+impl DecoderPool {
+    pub fn new(settings: DecoderSettings) -> Self {
+        Self {
+            state: Mutex::new(PoolState {
+                settings,
+                ..Default::default()
+            }),
+            returned: Condvar::new(),
+        }
+    }
+
+    pub fn set_settings(&self, settings: DecoderSettings) {
+        let mut st = self.state.lock().unwrap();
+        st.settings = settings;
+        while st.open > st.settings.max_open_decoders.max(1) && st.close_lru_idle(None) {}
+        self.returned.notify_all();
+    }
+
+    /// Checks out a decoder for `path`, preferring one already positioned just
+    /// before `ticks` so sequential requests keep decoding forward.
+    ///
+    /// Blocks while the source (or the whole pool) is at its handle limit and
+    /// every decoder is busy, so call this from a worker job.
+    pub fn acquire(self: &Arc<Self>, path: &str, ticks: Option<u64>) -> MediaResult<DecoderLease> {
+        self.checkout(path, ticks, true)
+            .map(|lease| lease.expect("waiting checkout always returns a decoder"))
+    }
+
+    /// Like [`acquire`](Self::acquire), but returns `Ok(None)` instead of
+    /// waiting when no decoder for `path` is free. Opening a new one still
+    /// reads the file, so this too belongs in a job.
+    pub fn try_acquire(
+        self: &Arc<Self>,
+        path: &str,
+        ticks: Option<u64>,
+    ) -> MediaResult<Option<DecoderLease>> {
+        self.checkout(path, ticks, false)
+    }
+
+    fn checkout(
+        self: &Arc<Self>,
+        path: &str,
+        ticks: Option<u64>,
+        wait: bool,
+    ) -> MediaResult<Option<DecoderLease>> {
+        let mut st = self.state.lock().unwrap();
+        loop {
+            st.clock += 1;
+            let clock = st.clock;
+            let settings = st.settings;
+            let entry = st.sources.entry(path.to_string()).or_default();
+            entry.last_used = clock;
+
+            if !entry.idle.is_empty() {
+                let idx = best_idle(&entry.idle, ticks);
+                let decoder = entry.idle.swap_remove(idx);
+                entry.busy += 1;
+                return Ok(Some(self.lease(path, decoder)));
+            }
+
+            let busy = entry.busy;
+            if busy < settings.max_decoders_per_source.max(1)
+                && (st.open < settings.max_open_decoders.max(1) || st.close_lru_idle(Some(path)))
+            {
+                st.open += 1;
+                st.sources.get_mut(path).unwrap().busy += 1;
+                drop(st);
+                return match Decoder::new(Path::new(path)) {
+                    Ok(decoder) => {
+                        let mut st = self.state.lock().unwrap();
+                        if let Some(entry) = st.sources.get_mut(path) {
+                            entry.info =
+                                Some((decoder.frame_rate(), decoder.size(), decoder.geometry()));
+                        }
+                        Ok(Some(self.lease(path, decoder)))
+                    }
+                    Err(e) => {
+                        self.release(path, None);
+                        Err(e)
+                    }
+                };
+            }
+
+            if !wait {
+                return Ok(None);
+            }
+            st = self.returned.wait(st).unwrap();
+        }
+    }
+
+    /// Frame rate, size and geometry of `path`, opening a decoder the first time it is asked.
+    /// That may block on I/O and on a free decoder, so only call it from a job.
+    pub fn source_info(self: &Arc<Self>, path: &str) -> MediaResult<SourceInfo> {
+        if let Some(info) = self.cached_info(path) {
+            return Ok(info);
+        }
+        let decoder = self.acquire(path, None)?;
+        Ok((decoder.frame_rate(), decoder.size(), decoder.geometry()))
+    }
+
+    /// [`source_info`](Self::source_info) if a decoder for `path` has been
+    /// opened before, without blocking.
+    pub fn cached_info(&self, path: &str) -> Option<SourceInfo> {
+        self.state
+            .lock()
+            .unwrap()
+            .sources
+            .get(path)
+            .and_then(|e| e.info)
+    }
+
+    /// Closes every idle decoder. Decoders that are checked out close when returned.
+    pub fn clear(&self) {
+        let mut st = self.state.lock().unwrap();
+        let closed: usize = st.sources.values().map(|e| e.idle.len()).sum();
+        st.open -= closed;
+        st.sources.retain(|_, e| {
+            e.idle.clear();
+            e.busy > 0
+        });
+        self.returned.notify_all();
+    }
+
+    fn lease(self: &Arc<Self>, path: &str, decoder: Decoder) -> DecoderLease {
+        DecoderLease {
+            pool: self.clone(),
+            path: path.to_string(),
+            decoder: Some(decoder),
+        }
+    }
+
+    fn release(&self, path: &str, decoder: Option<Decoder>) {
+        let mut guard = self.state.lock().unwrap();
+        let st = &mut *guard;
+        let over_limit = st.open > st.settings.max_open_decoders.max(1);
+        match st.sources.get_mut(path) {
+            Some(entry) => {
+                entry.busy -= 1;
+                match decoder {
+                    Some(decoder) if !over_limit => entry.idle.push(decoder),
+                    _ => st.open -= 1,
+                }
+            }
+            // The pool was cleared while this decoder was out.
+            None => st.open -= 1,
+        }
+        drop(guard);
+        self.returned.notify_all();
+    }
+}
+
+impl PoolState {
+    /// Closes one idle decoder from the least recently used source, preferring
+    /// sources other than `keep`. Returns `false` if nothing was idle.
+    fn close_lru_idle(&mut self, keep: Option<&str>) -> bool {
+        let victim = self
+            .sources
+            .iter()
+            .filter(|(_, e)| !e.idle.is_empty())
+            .min_by_key(|(path, e)| (keep == Some(path.as_str()), e.last_used))
+            .map(|(path, _)| path.clone());
+        let Some(path) = victim else {
+            return false;
+        };
+        let entry = self.sources.get_mut(&path).unwrap();
+        entry.idle.remove(0);
+        self.open -= 1;
+        if entry.idle.is_empty() && entry.busy == 0 {
+            self.sources.remove(&path);
+        }
+        true
+    }
+}
+
+/// Picks the idle decoder whose cursor sits closest before `ticks`.
+fn best_idle(idle: &[Decoder], ticks: Option<u64>) -> usize {
+    let Some(ticks) = ticks else {
+        return idle.len() - 1;
+    };
+    idle.iter()
+        .enumerate()
+        .min_by_key(|(_, d)| match d.cursor() {
+            Some(c) if c <= ticks => ticks - c,
+            _ => u64::MAX,
+        })
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+impl Deref for DecoderLease {
+    type Target = Decoder;
+
+    fn deref(&self) -> &Decoder {
+        self.decoder.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for DecoderLease {
+    fn deref_mut(&mut self) -> &mut Decoder {
+        self.decoder.as_mut().unwrap()
+    }
+}
+
+impl Drop for DecoderLease {
+    fn drop(&mut self) {
+        self.pool.release(&self.path, self.decoder.take());
+    }
+}
+
+#[cfg(all(test, not(feature = "real_ffmpeg")))]
+mod tests {
+    use super::*;
+
+    const A: &str = "synthetic:bars?size=16x16&rate=24";
+    const B: &str = "synthetic:bars?size=16x16&rate=25";
+    const C: &str = "synthetic:bars?size=16x16&rate=30";
+
+    fn pool(max_open_decoders: usize, max_decoders_per_source: usize) -> Arc<DecoderPool> {
+        Arc::new(DecoderPool::new(DecoderSettings {
+            max_open_decoders,
+            max_decoders_per_source,
+        }))
+    }
+
+    fn open(pool: &DecoderPool) -> usize {
+        pool.state.lock().unwrap().open
+    }
+
+    fn idle(pool: &DecoderPool, path: &str) -> Option<usize> {
+        pool.state
+            .lock()
+            .unwrap()
+            .sources
+            .get(path)
+            .map(|e| e.idle.len())
+    }
+
+    #[test]
+    fn open_handles_stay_within_the_limit() {
+        let pool = pool(2, 2);
+        let first = pool.acquire(A, None).unwrap();
+        let second = pool.acquire(A, None).unwrap();
+        assert_eq!(open(&pool), 2);
+
+        // Nothing is idle to close for B, so it has to wait.
+        assert!(pool.try_acquire(B, None).unwrap().is_none());
+        assert!(pool.try_acquire(A, None).unwrap().is_none());
+        assert_eq!(open(&pool), 2);
+
+        drop(first);
+        let b = pool.try_acquire(B, None).unwrap();
+        assert!(b.is_some());
+        assert_eq!(open(&pool), 2);
+        assert_eq!(idle(&pool, A), Some(0));
+        drop((second, b));
+        assert_eq!(open(&pool), 2);
+    }
+
+    #[test]
+    fn least_recently_used_idle_decoder_is_closed() {
+        let pool = pool(2, 1);
+        drop(pool.acquire(A, None).unwrap());
+        drop(pool.acquire(B, None).unwrap());
+        // Using A again leaves B as the least recently used.
+        drop(pool.acquire(A, None).unwrap());
+        assert_eq!(open(&pool), 2);
+
+        let c = pool.acquire(C, None).unwrap();
+        assert_eq!(open(&pool), 2);
+        assert_eq!(idle(&pool, A), Some(1));
+        assert_eq!(idle(&pool, B), None);
+        assert!(pool.cached_info(B).is_none());
+        drop(c);
+        assert_eq!(idle(&pool, C), Some(1));
+    }
+
+    #[test]
+    fn returned_decoders_are_reused() {
+        let pool = pool(4, 2);
+        let lease = pool.acquire(A, None).unwrap();
+        assert_eq!(idle(&pool, A), Some(0));
+        drop(lease);
+        assert_eq!(idle(&pool, A), Some(1));
+
+        let _lease = pool.acquire(A, None).unwrap();
+        assert_eq!(open(&pool), 1);
+        assert_eq!(idle(&pool, A), Some(0));
+    }
+
+    #[test]
+    fn decoders_returned_over_the_limit_are_closed() {
+        let pool = pool(4, 2);
+        let first = pool.acquire(A, None).unwrap();
+        let second = pool.acquire(A, None).unwrap();
+        pool.set_settings(DecoderSettings {
+            max_open_decoders: 1,
+            max_decoders_per_source: 2,
+        });
+        assert_eq!(open(&pool), 2);
+
+        drop(first);
+        assert_eq!((open(&pool), idle(&pool, A)), (1, Some(0)));
+        drop(second);
+        assert_eq!((open(&pool), idle(&pool, A)), (1, Some(1)));
+    }
+}
@@ -0,0 +1,286 @@
+//! Read-ahead decoding while the playhead moves.
+//!
+//! Every world update the playhead's velocity is measured against wall time.
+//! While it moves, the next frames of each clip under it are decoded into the
+//! frame cache by background jobs, so `schedule_render` finds them ready.
+//! A jump (seek, scrub against the direction of play) invalidates all
+//! queued read-ahead; jobs check their generation before touching a decoder.
+
+use crate::{
+    cache::{FrameCache, FrameKey},
+    index::FrameIndex,
+    pool::DecoderPool,
+};
+use lunaris_api::consts::tps;
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Instant,
+};
+
+/// Playhead movement since the last update, in timeline ticks per second of wall time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Motion {
+    pub position: u64,
+    pub velocity: f64,
+}
+
+impl Motion {
+    /// +1 playing forward, -1 backward.
+    pub fn direction(&self) -> i64 {
+        if self.velocity < 0.0 { -1 } else { 1 }
+    }
+
+    /// Frames skipped per displayed frame at this speed; 1 up to real time.
+    pub fn stride(&self) -> u64 {
+        (self.velocity.abs() / tps() as f64).floor().max(1.0) as u64
+    }
+}
+
+#[derive(Default)]
+pub struct Prefetcher {
+    last: Option<(u64, Instant)>,
+    velocity: f64,
+    generation: Arc<AtomicU64>,
+    /// Keys with a job queued or running, so they are not submitted twice,
+    /// with the generation that claimed them.
+    pending: Arc<Mutex<HashMap<FrameKey, u64>>>,
+}
+
+/// Lets a queued job find out whether it is still wanted. Dropping it
+/// releases its key.
+pub struct Ticket {
+    generation: Arc<AtomicU64>,
+    issued: u64,
+    pending: Arc<Mutex<HashMap<FrameKey, u64>>>,
+    key: FrameKey,
+}
+
+impl Ticket {
+    pub fn key(&self) -> &FrameKey {
+        &self.key
+    }
+
+    pub fn is_current(&self) -> bool {
+        self.generation.load(Ordering::Acquire) == self.issued
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        // A cancelled job finishing late must not release the key for the
+        // job that claimed it again after the jump.
+        let mut pending = self.pending.lock().unwrap();
+        if pending.get(&self.key) == Some(&self.issued) {
+            pending.remove(&self.key);
+        }
+    }
+}
+
+impl Prefetcher {
+    /// Records the playhead at `position`. Returns its motion while it is
+    /// playing; `None` when it stands still or just jumped.
+    pub fn observe(&mut self, position: u64) -> Option<Motion> {
+        let now = Instant::now();
+        let Some((last_pos, last_at)) = self.last.replace((position, now)) else {
+            return None;
+        };
+        let dt = now.duration_since(last_at).as_secs_f64();
+        if position == last_pos || dt <= 0.0 {
+            // Updates can come faster than the playhead advances; keep the
+            // last speed unless it has been still for a while.
+            if dt > 0.25 {
+                self.velocity = 0.0;
+            }
+            return (self.velocity != 0.0).then_some(Motion {
+                position,
+                velocity: self.velocity,
+            });
+        }
+
+        let delta = position as f64 - last_pos as f64;
+        let expected = self.velocity * dt;
+        let reversed = self.velocity != 0.0 && delta.signum() != self.velocity.signum();
+        if reversed || (delta - expected).abs() > tps() as f64 / 2.0 && self.velocity != 0.0 {
+            self.cancel();
+            self.velocity = 0.0;
+            // Measure afresh from the new position.
+            return None;
+        }
+        // Light smoothing; frame pacing of the UI is uneven.
+        let measured = delta / dt;
+        self.velocity = if self.velocity == 0.0 {
+            measured
+        } else {
+            self.velocity * 0.5 + measured * 0.5
+        };
+        Some(Motion {
+            position,
+            velocity: self.velocity,
+        })
+    }
+
+    /// Invalidates every queued read-ahead job.
+    pub fn cancel(&mut self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        self.pending.lock().unwrap().clear();
+    }
+
+    /// Claims `key` for a new job, unless one is already queued for it.
+    pub fn claim(&self, key: FrameKey) -> Option<Ticket> {
+        let mut pending = self.pending.lock().unwrap();
+        let issued = self.generation.load(Ordering::Acquire);
+        if pending.get(&key) == Some(&issued) {
+            return None;
+        }
+        pending.insert(key.clone(), issued);
+        Some(Ticket {
+            generation: self.generation.clone(),
+            issued,
+            pending: self.pending.clone(),
+            key,
+        })
+    }
+
+    pub fn reset(&mut self) {
+        self.cancel();
+        self.last = None;
+        self.velocity = 0.0;
+    }
+}
+
+/// Body of a read-ahead job: decodes the ticket's frame at source `ticks` of
+/// `path` into `cache`. Gives up when the ticket went stale or every decoder
+/// of `path` is busy; dropping the ticket lets a later update claim the frame
+/// again. Returns whether a frame was cached.
+pub fn read_ahead(
+    ticket: Ticket,
+    decoders: &Arc<DecoderPool>,
+    cache: &FrameCache,
+    path: &str,
+    ticks: u64,
+    index: Option<Arc<FrameIndex>>,
+) -> bool {
+    if !ticket.is_current() {
+        return false;
+    }
+    // Read-ahead never waits for renders to hand a decoder back.
+    let Ok(Some(mut decoder)) = decoders.try_acquire(path, Some(ticks)) else {
+        return false;
+    };
+    // Opening a decoder may have outlasted the jump.
+    if !ticket.is_current() {
+        return false;
+    }
+    if let Some(index) = index {
+        decoder.set_index(index);
+    }
+    let key = ticket.key();
+    match decoder.decode_frame(ticks, key.spec, key.view) {
+        Ok(frame) => {
+            cache.insert(key.clone(), frame);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+#[cfg(all(test, not(feature = "real_ffmpeg")))]
+mod tests {
+    use super::*;
+    use crate::{
+        components::{DecoderSettings, FrameCacheSettings},
+        frame::{FrameFormat, FrameSpec},
+        geometry::FrameView,
+        time::{self, Rational},
+    };
+
+    const PATH: &str = "synthetic:bars?size=64x36&rate=24&overlay=0";
+
+    fn key(frame: u64) -> FrameKey {
+        FrameKey {
+            source: PATH.to_string(),
+            frame,
+            spec: FrameSpec {
+                format: FrameFormat::Rgba8,
+                width: 64,
+                height: 36,
+            },
+            view: FrameView::default(),
+        }
+    }
+
+    fn ticks(frame: u64) -> u64 {
+        time::frame_to_ticks(frame, Rational::new(24, 1))
+    }
+
+    fn pool() -> Arc<DecoderPool> {
+        Arc::new(DecoderPool::new(DecoderSettings {
+            max_open_decoders: 4,
+            max_decoders_per_source: 1,
+        }))
+    }
+
+    #[test]
+    fn claimed_keys_are_not_claimed_twice() {
+        let prefetch = Prefetcher::default();
+        let ticket = prefetch.claim(key(1)).unwrap();
+        assert!(prefetch.claim(key(1)).is_none());
+        assert!(prefetch.claim(key(2)).is_some());
+
+        drop(ticket);
+        assert!(prefetch.claim(key(1)).is_some());
+    }
+
+    #[test]
+    fn stale_ticket_keeps_key_claimed_after_jump() {
+        let mut prefetch = Prefetcher::default();
+        let stale = prefetch.claim(key(1)).unwrap();
+        prefetch.cancel();
+        assert!(!stale.is_current());
+
+        let fresh = prefetch.claim(key(1)).unwrap();
+        assert!(fresh.is_current());
+        // The cancelled job finishing late leaves the new claim alone.
+        drop(stale);
+        assert!(prefetch.claim(key(1)).is_none());
+
+        drop(fresh);
+        assert!(prefetch.claim(key(1)).is_some());
+    }
+
+    #[test]
+    fn stale_ticket_never_opens_a_decoder() {
+        let mut prefetch = Prefetcher::default();
+        let decoders = pool();
+        let cache = FrameCache::new(FrameCacheSettings::default());
+        let ticket = prefetch.claim(key(1)).unwrap();
+        prefetch.cancel();
+
+        assert!(!read_ahead(ticket, &decoders, &cache, PATH, ticks(1), None));
+        assert!(decoders.cached_info(PATH).is_none());
+        assert!(!cache.contains(&key(1)));
+    }
+
+    #[test]
+    fn read_ahead_skips_busy_decoders() {
+        let prefetch = Prefetcher::default();
+        let decoders = pool();
+        let cache = FrameCache::new(FrameCacheSettings::default());
+
+        // A render holds the only decoder the source may have.
+        let render = decoders.acquire(PATH, None).unwrap();
+        let ticket = prefetch.claim(key(1)).unwrap();
+        assert!(!read_ahead(ticket, &decoders, &cache, PATH, ticks(1), None));
+        assert!(!cache.contains(&key(1)));
+
+        // The key was released, so the next update tries again.
+        drop(render);
+        let ticket = prefetch.claim(key(1)).unwrap();
+        assert!(read_ahead(ticket, &decoders, &cache, PATH, ticks(1), None));
+        assert!(cache.contains(&key(1)));
+    }
+}
@@ -0,0 +1,179 @@
+//! Reads stream metadata without decoding, so clips can be sized and described
+//! before anything is rendered.
+
+use crate::{
+    components::MediaInfo,
+    error::{MediaError, MediaResult},
+    geometry::FieldOrder,
+    time::Rational,
+};
+use std::path::Path;
+
+/// Probes the container at `path` for its best video and audio streams.
+pub fn probe(path: &Path) -> MediaResult<MediaInfo> {
+    let mut info = probe_media(path)?;
+    info.file_size = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    Ok(info)
+}
+
+fn probe_media(path: &Path) -> MediaResult<MediaInfo> {
+    if crate::image_source::is_image_path(path) {
+        return probe_image(path);
+    }
+    #[cfg(feature = "real_ffmpeg")]
+    {
+        probe_ffmpeg(path).map_err(|e| MediaError::from_ffmpeg(&path.display().to_string(), e))
+    }
+    #[cfg(not(feature = "real_ffmpeg"))]
+    {
+        // Mirrors what the mock decoder produces.
+        let name = path.to_string_lossy();
+        let source = crate::pattern::SyntheticSource::from_path(&name).map_err(|e| {
+            MediaError::Unsupported {
+                path: name.to_string(),
+                reason: e.to_string(),
+            }
+        })?;
+        Ok(MediaInfo {
+            duration: source.duration_secs * lunaris_api::consts::tps(),
+            frame_rate: Some(source.frame_rate),
+            time_base: Some(Rational::new(source.frame_rate.den, source.frame_rate.num)),
+            width: source.width,
+            height: source.height,
+            pixel_format: Some("rgba".to_string()),
+            color_space: Some("bt709".to_string()),
+            rotation: 0,
+            field_order: FieldOrder::Progressive,
+            pixel_aspect: Rational::new(1, 1),
+            video_codec: Some("synthetic".to_string()),
+            audio_codec: Some("synthetic".to_string()),
+            sample_rate: 48_000,
+            audio_channels: 2,
+            channel_layout: Some("stereo".to_string()),
+            file_size: 0,
+        })
+    }
+}
+
+/// Stills and sequences: size from the first image, duration at the default
+/// sequence rate (one frame for a still).
+fn probe_image(path: &Path) -> MediaResult<MediaInfo> {
+    let source = crate::image_source::ImageSource::open(path)?;
+    let rate = source.frame_rate();
+    let (width, height) = source.size();
+    Ok(MediaInfo {
+        duration: crate::time::frame_to_ticks(source.frame_count(), rate),
+        frame_rate: Some(rate),
+        time_base: Some(Rational::new(rate.den, rate.num)),
+        width,
+        height,
+        pixel_format: Some("rgba".to_string()),
+        color_space: None,
+        rotation: 0,
+        field_order: FieldOrder::Progressive,
+        pixel_aspect: Rational::new(1, 1),
+        video_codec: path
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase()),
+        audio_codec: None,
+        sample_rate: 0,
+        audio_channels: 0,
+        channel_layout: None,
+        file_size: 0,
+    })
+}
+
+#[cfg(feature = "real_ffmpeg")]
+fn probe_ffmpeg(path: &Path) -> std::result::Result<MediaInfo, ffmpeg_next::Error> {
+    use ffmpeg_next::{codec, ffi, format, media};
+
+    let input = format::input(&path)?;
+    let to_rational = |r: ffmpeg_next::Rational| {
+        Rational::new(i64::from(r.numerator()), i64::from(r.denominator()))
+    };
+
+    let mut info = MediaInfo {
+        duration: 0,
+        frame_rate: None,
+        time_base: None,
+        width: 0,
+        height: 0,
+        pixel_format: None,
+        color_space: None,
+        rotation: 0,
+        field_order: FieldOrder::Progressive,
+        pixel_aspect: Rational::new(1, 1),
+        video_codec: None,
+        audio_codec: None,
+        sample_rate: 0,
+        audio_channels: 0,
+        channel_layout: None,
+        file_size: 0,
+    };
+    if input.duration() > 0 {
+        info.duration = (i128::from(input.duration()) * i128::from(lunaris_api::consts::tps())
+            / i128::from(ffi::AV_TIME_BASE)) as u64;
+    }
+
+    if let Some(stream) = input.streams().best(media::Type::Video) {
+        let params = stream.parameters();
+        info.video_codec = Some(params.id().name().to_string());
+        info.time_base = Some(to_rational(stream.time_base())).filter(Rational::is_valid);
+        info.frame_rate = [stream.avg_frame_rate(), stream.rate()]
+            .into_iter()
+            .map(to_rational)
+            .find(Rational::is_valid);
+        let decoder = codec::context::Context::from_parameters(params)?
+            .decoder()
+            .video()?;
+        info.width = decoder.width();
+        info.height = decoder.height();
+        info.pixel_format = Some(format!("{:?}", decoder.format()).to_lowercase());
+        info.color_space = Some(format!("{:?}", decoder.color_space()).to_lowercase());
+        let geometry = crate::geometry::from_stream(&stream);
+        info.rotation = geometry.rotation;
+        info.field_order = geometry.field_order;
+        info.pixel_aspect = geometry.pixel_aspect;
+    }
+
+    if let Some(stream) = input.streams().best(media::Type::Audio) {
+        let params = stream.parameters();
+        info.audio_codec = Some(params.id().name().to_string());
+        let decoder = codec::context::Context::from_parameters(params)?
+            .decoder()
+            .audio()?;
+        info.sample_rate = decoder.rate();
+        info.audio_channels = decoder.channels();
+        info.channel_layout = Some(format!("{:?}", decoder.channel_layout()).to_lowercase());
+    }
+
+    Ok(info)
+}
+
+/// Clockwise display rotation in degrees, from the display matrix or the
+/// legacy `rotate` tag.
+#[cfg(feature = "real_ffmpeg")]
+pub(crate) fn rotation(stream: &ffmpeg_next::format::stream::Stream) -> i32 {
+    use ffmpeg_next::{ffi, packet::side_data::Type};
+
+    let from_matrix = stream
+        .side_data()
+        .find(|sd| sd.kind() == Type::DisplayMatrix)
+        .filter(|sd| sd.data().len() >= 9 * 4)
+        .map(|sd| {
+            // The matrix rotates counter-clockwise.
+            // SAFETY: the side data holds a 3x3 i32 matrix, checked above.
+            let ccw = unsafe { ffi::av_display_rotation_get(sd.data().as_ptr() as *const i32) };
+            -ccw
+        });
+    let degrees = from_matrix.or_else(|| {
+        stream
+            .metadata()
+            .get("rotate")
+            .and_then(|r| r.parse::<f64>().ok())
+    });
+    match degrees {
+        Some(d) if d.is_finite() => (d.round() as i32).rem_euclid(360),
+        _ => 0,
+    }
+}
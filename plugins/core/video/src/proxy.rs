@@ -0,0 +1,199 @@
+//! Low-resolution, intra-only proxy files for smooth editing of long-GOP sources.
+//!
+//! Proxies keep the source timestamps, so a tick maps to the same frame in
+//! the proxy and in the original and can be swapped in at render time.
+
+use crate::{
+    components::ProxyStatus,
+    error::{MediaError, MediaResult},
+    frame::Downscale,
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, mpsc},
+};
+
+/// Proxies are decoded at most at this size.
+pub const PROXY_SIZE: Downscale = Downscale {
+    scale: None,
+    max_width: Some(960),
+    max_height: Some(540),
+};
+
+/// `<dir>/Proxies/<stem>_proxy.mov` for a source at `<dir>/<stem>.<ext>`.
+pub fn default_proxy_path(source: &Path) -> PathBuf {
+    let stem = source
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "source".to_string());
+    source
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join("Proxies")
+        .join(format!("{stem}_proxy.mov"))
+}
+
+/// Finished proxies by original path, shared with the render path.
+#[derive(Default, Clone)]
+pub struct ProxyRegistry {
+    ready: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl ProxyRegistry {
+    pub fn get(&self, source: &str) -> Option<String> {
+        self.ready.lock().unwrap().get(source).cloned()
+    }
+
+    pub fn insert(&self, source: String, proxy: String) {
+        self.ready.lock().unwrap().insert(source, proxy);
+    }
+
+    pub fn clear(&self) {
+        self.ready.lock().unwrap().clear();
+    }
+}
+
+/// Outcome of a background transcode, reported back to `update_world`.
+pub struct ProxyResult {
+    pub source: String,
+    pub proxy: String,
+    pub status: ProxyStatus,
+}
+
+/// Builds the job body that transcodes `source` into `proxy` and reports back on `done`.
+pub fn transcode_job(
+    source: String,
+    proxy: String,
+    done: mpsc::Sender<ProxyResult>,
+) -> impl FnOnce() + Send + 'static {
+    move || {
+        let status = match transcode(Path::new(&source), Path::new(&proxy)) {
+            Ok(()) => ProxyStatus::Ready,
+            Err(e) => ProxyStatus::Failed(e.to_string()),
+        };
+        let _ = done.send(ProxyResult {
+            source,
+            proxy,
+            status,
+        });
+    }
+}
+
+/// Transcodes the best video stream of `source` into an MJPEG proxy at
+/// [`PROXY_SIZE`], keeping the original timestamps.
+pub fn transcode(source: &Path, proxy: &Path) -> MediaResult<()> {
+    if let Some(dir) = proxy.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| MediaError::from_io(dir, &e))?;
+    }
+
+    #[cfg(feature = "real_ffmpeg")]
+    {
+        transcode_ffmpeg(source, proxy)
+            .map_err(|e| MediaError::from_ffmpeg(&source.display().to_string(), e))
+    }
+    #[cfg(not(feature = "real_ffmpeg"))]
+    {
+        Err(MediaError::Unsupported {
+            path: source.display().to_string(),
+            reason: "Proxy generation needs the real_ffmpeg feature".to_string(),
+        })
+    }
+}
+
+#[cfg(feature = "real_ffmpeg")]
+fn transcode_ffmpeg(source: &Path, proxy: &Path) -> std::result::Result<(), ffmpeg_next::Error> {
+    use ffmpeg_next::{
+        Packet, codec, encoder,
+        format::{self, Pixel},
+        media,
+        software::scaling,
+        util::frame::Video,
+    };
+
+    let mut ictx = format::input(&source)?;
+    let ist = ictx
+        .streams()
+        .best(media::Type::Video)
+        .ok_or(ffmpeg_next::Error::StreamNotFound)?;
+    let ist_index = ist.index();
+    let ist_tb = ist.time_base();
+    let rate = ist.avg_frame_rate();
+    let mut decoder = codec::context::Context::from_parameters(ist.parameters())?
+        .decoder()
+        .video()?;
+    let (width, height) = PROXY_SIZE.apply(decoder.width(), decoder.height());
+
+    let mut octx = format::output(&proxy)?;
+    let codec = encoder::find(codec::Id::MJPEG).ok_or(ffmpeg_next::Error::EncoderNotFound)?;
+    let global_header = octx.format().flags().contains(format::Flags::GLOBAL_HEADER);
+    let mut ost = octx.add_stream(codec)?;
+    let mut enc = codec::context::Context::new_with_codec(codec)
+        .encoder()
+        .video()?;
+    enc.set_width(width);
+    enc.set_height(height);
+    enc.set_format(Pixel::YUVJ420P);
+    enc.set_time_base(ist_tb);
+    enc.set_frame_rate(Some(rate));
+    // MJPEG is intra-only; every frame is a keyframe.
+    enc.set_gop(1);
+    if global_header {
+        enc.set_flags(codec::Flags::GLOBAL_HEADER);
+    }
+    let mut encoder = enc.open_as(codec)?;
+    ost.set_parameters(&encoder);
+    ost.set_time_base(ist_tb);
+    octx.write_header()?;
+    let ost_tb = octx.stream(0).unwrap().time_base();
+
+    let mut scaler = scaling::Context::get(
+        decoder.format(),
+        decoder.width(),
+        decoder.height(),
+        Pixel::YUVJ420P,
+        width,
+        height,
+        scaling::flag::AREA,
+    )?;
+
+    let write_packets = |encoder: &mut encoder::Video,
+                         octx: &mut format::context::Output|
+     -> std::result::Result<(), ffmpeg_next::Error> {
+        let mut packet = Packet::empty();
+        while encoder.receive_packet(&mut packet).is_ok() {
+            packet.set_stream(0);
+            packet.rescale_ts(ist_tb, ost_tb);
+            packet.write_interleaved(octx)?;
+        }
+        Ok(())
+    };
+    let mut encode_decoded = |decoder: &mut ffmpeg_next::decoder::Video,
+                              encoder: &mut encoder::Video,
+                              octx: &mut format::context::Output|
+     -> std::result::Result<(), ffmpeg_next::Error> {
+        let mut decoded = Video::empty();
+        while decoder.receive_frame(&mut decoded).is_ok() {
+            let mut scaled = Video::empty();
+            scaler.run(&decoded, &mut scaled)?;
+            scaled.set_pts(decoded.timestamp().or(decoded.pts()));
+            encoder.send_frame(&scaled)?;
+            write_packets(encoder, octx)?;
+        }
+        Ok(())
+    };
+
+    for (stream, packet) in ictx.packets() {
+        if stream.index() != ist_index {
+            continue;
+        }
+        decoder.send_packet(&packet)?;
+        encode_decoded(&mut decoder, &mut encoder, &mut octx)?;
+    }
+    decoder.send_eof()?;
+    encode_decoded(&mut decoder, &mut encoder, &mut octx)?;
+    encoder.send_eof()?;
+    write_packets(&mut encoder, &mut octx)?;
+    octx.write_trailer()?;
+    Ok(())
+}
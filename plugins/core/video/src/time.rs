@@ -0,0 +1,146 @@
+use lunaris_api::consts::tps;
+
+/// Exact rational number, used for frame rates and stream time bases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rational {
+    pub num: i64,
+    pub den: i64,
+}
+
+impl Rational {
+    pub const fn new(num: i64, den: i64) -> Self {
+        Self { num, den }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.num > 0 && self.den > 0
+    }
+
+    /// Builds a frame rate from a float, snapping NTSC-style rates
+    /// (23.976, 29.97, 59.94, ...) to their exact `N*1000/1001` form.
+    pub fn from_fps(fps: f64) -> Option<Self> {
+        if !fps.is_finite() || fps <= 0.0 {
+            return None;
+        }
+        let rounded = fps.round();
+        if (fps - rounded).abs() < 1e-6 {
+            return Some(Self::new(rounded as i64, 1));
+        }
+        let ntsc = (fps * 1001.0 / 1000.0).round();
+        if (ntsc * 1000.0 / 1001.0 - fps).abs() < 5e-3 {
+            return Some(Self::new(ntsc as i64 * 1000, 1001));
+        }
+        Some(Self::new((fps * 1000.0).round() as i64, 1000))
+    }
+
+    /// Parses `"24000/1001"`, `"25"` or `"23.976"`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        if let Some((num, den)) = s.split_once('/') {
+            let r = Self::new(num.trim().parse().ok()?, den.trim().parse().ok()?);
+            return r.is_valid().then_some(r);
+        }
+        Self::from_fps(s.parse().ok()?)
+    }
+}
+
+/// Start of frame `frame` at `rate`, in ticks.
+///
+/// Rounds up, so converting the result to a stream timestamp with
+/// [`ticks_to_pts`] never lands on the previous frame.
+pub fn frame_to_ticks(frame: u64, rate: Rational) -> u64 {
+    let n = i128::from(frame) * i128::from(tps()) * i128::from(rate.den);
+    let d = i128::from(rate.num);
+    ((n + d - 1) / d) as u64
+}
+
+/// Index of the frame at `rate` shown at `ticks`.
+pub fn ticks_to_frame(ticks: u64, rate: Rational) -> u64 {
+    (i128::from(ticks) * i128::from(rate.num) / (i128::from(tps()) * i128::from(rate.den))) as u64
+}
+
+/// Converts ticks to a timestamp in `time_base` units, rounding down.
+pub fn ticks_to_pts(ticks: u64, time_base: Rational) -> i64 {
+    (i128::from(ticks) * i128::from(time_base.den)
+        / (i128::from(tps()) * i128::from(time_base.num))) as i64
+}
+
+/// Converts a timestamp in `time_base` units to ticks, rounding down.
+pub fn pts_to_ticks(pts: i64, time_base: Rational) -> u64 {
+    (i128::from(pts.max(0)) * i128::from(tps()) * i128::from(time_base.num)
+        / i128::from(time_base.den)) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATES: [Rational; 3] = [
+        Rational::new(24000, 1001),
+        Rational::new(30000, 1001),
+        Rational::new(25, 1),
+    ];
+
+    /// The first frames and some deep into a day-long recording.
+    fn frames() -> impl Iterator<Item = u64> {
+        (0..2000).chain(2_589_400..2_591_400)
+    }
+
+    #[test]
+    fn frames_round_trip_through_ticks() {
+        for rate in RATES {
+            for frame in frames() {
+                let ticks = frame_to_ticks(frame, rate);
+                assert_eq!(ticks_to_frame(ticks, rate), frame, "{rate:?} frame {frame}");
+                if frame > 0 {
+                    // The tick before a frame's start still shows the previous frame.
+                    assert_eq!(ticks_to_frame(ticks - 1, rate), frame - 1, "{rate:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn frame_starts_land_on_their_pts() {
+        for rate in RATES {
+            // Streams commonly count in frame durations.
+            let time_base = Rational::new(rate.den, rate.num);
+            for frame in frames() {
+                let pts = ticks_to_pts(frame_to_ticks(frame, rate), time_base);
+                assert_eq!(pts, frame as i64, "{rate:?} frame {frame}");
+                // Back to ticks rounds down, to at most a tick before the start.
+                let start = frame_to_ticks(frame, rate);
+                assert!(
+                    start - pts_to_ticks(pts, time_base) <= 1,
+                    "{rate:?} frame {frame}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn pts_convert_in_a_90khz_time_base() {
+        let time_base = Rational::new(1, 90_000);
+        assert_eq!(pts_to_ticks(90_000, time_base), tps());
+        assert_eq!(ticks_to_pts(tps(), time_base), 90_000);
+        // 30 NTSC frames of 3003 units each last 1.001 s.
+        assert_eq!(pts_to_ticks(30 * 3003, time_base), tps() * 1001 / 1000);
+        assert_eq!(ticks_to_pts(10 * tps(), time_base), 900_000);
+        // Timestamps before the stream start clamp to its first tick.
+        assert_eq!(pts_to_ticks(-3003, time_base), 0);
+    }
+
+    #[test]
+    fn rates_parse_to_exact_fractions() {
+        assert_eq!(
+            Rational::parse("24000/1001"),
+            Some(Rational::new(24000, 1001))
+        );
+        assert_eq!(Rational::parse("23.976"), Some(Rational::new(24000, 1001)));
+        assert_eq!(Rational::parse(" 29.97 "), Some(Rational::new(30000, 1001)));
+        assert_eq!(Rational::parse("25"), Some(Rational::new(25, 1)));
+        assert_eq!(Rational::parse("12.5"), Some(Rational::new(12500, 1000)));
+        assert_eq!(Rational::parse("0/1"), None);
+        assert_eq!(Rational::parse("-25"), None);
+    }
+}